@@ -4,6 +4,7 @@ let map_mapY = document.getElementById("map-mapY");
 let map_smooth = document.getElementById("map-smooth");
 
 let memory = [];
+let pathfinder = null;
 
 async function loadwasm() {
 	await wasm_bindgen("pkg/wasm_odessay_jps_bg.wasm")
@@ -17,6 +18,10 @@ function mapScale() {
 	let mX = Math.min(Math.floor(Math.max(Number(map_mapX.value), 1)), 1000);
 	let mY = Math.min(Math.floor(Math.max(Number(map_mapY.value), 1)), 1000);
 	memory = Array(mX * mY).fill(0);
+	if (pathfinder !== null) {
+		pathfinder.free();
+	}
+	pathfinder = new wasm_bindgen.Pathfinder(new Uint8Array(memory), mX, mY);
 	mapX = mX;
 	mapY = mY;
 	stX = 0;
@@ -108,6 +113,7 @@ function mapChangeStep(event, isStart) {
 				changeMode = 2;
 			} else {
 				changeMode = memory[yg * mapX + xg] ^= 1;
+				pathfinder.set_cell(xg, yg, changeMode === 1);
 			}
 		} else {
 			switch (changeMode) {
@@ -128,6 +134,7 @@ function mapChangeStep(event, isStart) {
 				default:
 					if (!(xg === edX && yg === edY) && !(xg === stX && yg === stY)) {
 						memory[yg * mapX + xg] = changeMode;
+						pathfinder.set_cell(xg, yg, changeMode === 1);
 					}
 			}
 		}
@@ -143,9 +150,10 @@ function mapChangeStop(event) {
 
 function mapCalc() {
 	let mode = map_smooth.checked;
-	let resu = wasm_bindgen.a_star_jps(new Int8Array(memory), mapX, mapY, stX, stY, edX, edY);
-	let sp = resu[0];
-	let path = resu.slice(1, sp * 2 + 1);
-	let smoothpath = resu.slice(sp * 2 + 1);
-	return Array.from(mode ? smoothpath : path);
+	try {
+		return Array.from(pathfinder.find_flat(stX, stY, edX, edY, mode));
+	} catch (e) {
+		console.error("find failed: " + wasm_bindgen.PathError[e]);
+		return [];
+	}
 }
@@ -0,0 +1,188 @@
+use crate::grid::{DiagonalMode, Grid};
+use crate::pos::Pos;
+
+// 按位存的可走格子：rows一行一行存，cols是转置的副本，一列一列存。
+// 第k个字的第i位是这一行（列）的第64k + i格，地图外面的位都是0，也就是墙
+const WORD: i32 = 64;
+
+// 一行（列）里第k个字，越界的都当成墙
+fn word(bits: &[u64], per: i32, lines: i32, line: i32, k: i32) -> u64 {
+    if line < 0 || line >= lines || k < 0 || k >= per {
+        return 0;
+    }
+    return bits[(line * per + k) as usize];
+}
+
+// 第i位变成后一格（i + 1）的值
+fn next_cells(bits: &[u64], per: i32, lines: i32, line: i32, k: i32) -> u64 {
+    return (word(bits, per, lines, line, k) >> 1)
+        | (word(bits, per, lines, line, k + 1) << (WORD - 1));
+}
+
+// 第i位变成前一格（i - 1）的值
+fn prev_cells(bits: &[u64], per: i32, lines: i32, line: i32, k: i32) -> u64 {
+    return (word(bits, per, lines, line, k) << 1)
+        | (word(bits, per, lines, line, k - 1) >> (WORD - 1));
+}
+
+impl Grid {
+    pub fn is_bit_packed(&self) -> bool {
+        return !self.rows.is_empty();
+    }
+
+    /// Keeps a one-bit-per-cell copy of which cells are walkable, by rows
+    /// and by columns, so that [`JpsSearch`](crate::JpsSearch) can scan
+    /// straight lines 64 cells at a time. Costs 2 bits per cell and is kept
+    /// up to date as the grid is edited. Not used on 4-connected grids.
+    pub fn set_bit_packed(&mut self, enabled: bool) {
+        self.rows = Vec::new();
+        self.cols = Vec::new();
+        if enabled {
+            self.rows
+                .resize((self.size.y * self.row_words()) as usize, 0);
+            self.cols
+                .resize((self.size.x * self.col_words()) as usize, 0);
+            self.rebuild_bits();
+        }
+    }
+
+    fn row_words(&self) -> i32 {
+        return (self.size.x + WORD - 1) / WORD;
+    }
+
+    fn col_words(&self) -> i32 {
+        return (self.size.y + WORD - 1) / WORD;
+    }
+
+    pub(crate) fn rebuild_bits(&mut self) {
+        if !self.is_bit_packed() {
+            return;
+        }
+        self.rows.iter_mut().for_each(|word| *word = 0);
+        self.cols.iter_mut().for_each(|word| *word = 0);
+        for y in 0..self.size.y {
+            for x in 0..self.size.x {
+                self.update_bit(pos!(x, y));
+            }
+        }
+    }
+
+    pub(crate) fn update_bit(&mut self, point: Pos) {
+        if !self.is_bit_packed() {
+            return;
+        }
+        let walkable = self.can_walk(point) as u64;
+        let row = (point.y * self.row_words() + point.x / WORD) as usize;
+        let bit = point.x % WORD;
+        self.rows[row] = self.rows[row] & !(1 << bit) | walkable << bit;
+        let col = (point.x * self.col_words() + point.y / WORD) as usize;
+        let bit = point.y % WORD;
+        self.cols[col] = self.cols[col] & !(1 << bit) | walkable << bit;
+    }
+
+    // 从from往dir（横或竖）走，返回到停下那一格的步数，以及那一格是跳点（true）
+    // 还是墙（false）。和一格一格扫时的强迫邻居规则相同
+    pub(crate) fn scan_line(&self, from: Pos, dir: Pos) -> (i32, bool) {
+        let (bits, per, lines, line, along) = if dir.y == 0 {
+            (&self.rows, self.row_words(), self.size.y, from.y, from.x)
+        } else {
+            (&self.cols, self.col_words(), self.size.x, from.x, from.y)
+        };
+        let forward = dir.x + dir.y > 0;
+        let start = along + dir.x + dir.y;
+        let mut k = start.div_euclid(WORD);
+        while k >= 0 && k < per {
+            let (a, b) = (line - 1, line + 1);
+            let (wa, wb) = (word(bits, per, lines, a, k), word(bits, per, lines, b, k));
+            let (na, nb) = (
+                next_cells(bits, per, lines, a, k),
+                next_cells(bits, per, lines, b, k),
+            );
+            let (pa, pb) = (
+                prev_cells(bits, per, lines, a, k),
+                prev_cells(bits, per, lines, b, k),
+            );
+            let (aheada, aheadb, behinda, behindb) = if forward {
+                (na, nb, pa, pb)
+            } else {
+                (pa, pb, na, nb)
+            };
+            let forced = match self.diagonal() {
+                DiagonalMode::Always | DiagonalMode::IfAtMostOneObstacle => {
+                    (!wa & aheada) | (!wb & aheadb)
+                }
+                // 不能切墙角时，刚绕过障碍物的那一格才是跳点
+                DiagonalMode::OnlyWhenNoObstacles | DiagonalMode::Never => {
+                    (wa & !behinda) | (wb & !behindb)
+                }
+            };
+            let walls = !word(bits, per, lines, line, k);
+            let mut stop = walls | forced;
+            if k == start.div_euclid(WORD) {
+                let bit = start.rem_euclid(WORD);
+                stop &= if forward {
+                    !0 << bit
+                } else {
+                    !0 >> (WORD - 1 - bit)
+                };
+            }
+            if stop != 0 {
+                let bit = if forward {
+                    stop.trailing_zeros() as i32
+                } else {
+                    WORD - 1 - stop.leading_zeros() as i32
+                };
+                let cell = k * WORD + bit;
+                return ((cell - along).abs(), walls & (1 << bit) == 0);
+            }
+            k += if forward { 1 } else { -1 };
+        }
+        // 整行都走完了，停在地图外面那一格
+        let cell = if forward { per * WORD } else { -1 };
+        return ((cell - along).abs(), false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{JpsSearch, SearchMode};
+    use crate::testing::{random_edit, random_grid, rebuilt, Random, DIAGONAL_MODES};
+
+    #[test]
+    fn edited_bits_match_a_fresh_grid() {
+        let mut random = Random::new(22);
+        // 宽高都跨过一个字的边界
+        for diagonal in DIAGONAL_MODES {
+            let mut grid = random_grid(&mut random, pos!(70, 66), 30, diagonal);
+            grid.set_bit_packed(true);
+            for _ in 0..300 {
+                random_edit(&mut random, &mut grid);
+                let fresh = rebuilt(&grid);
+                assert!(grid.rows == fresh.rows && grid.cols == fresh.cols);
+            }
+        }
+    }
+
+    #[test]
+    fn bit_packed_costs_match_astar() {
+        let mut random = Random::new(122);
+        let mut jps = JpsSearch::new();
+        let mut astar = JpsSearch::new();
+        astar.set_mode(SearchMode::AStar);
+        for diagonal in DIAGONAL_MODES {
+            let mut grid = random_grid(&mut random, pos!(70, 66), 25, diagonal);
+            grid.set_bit_packed(true);
+            for _ in 0..100 {
+                random_edit(&mut random, &mut grid);
+                let (begin, end) = (random.pos(&grid), random.pos(&grid));
+                if !grid.can_walk(begin) || !grid.can_walk(end) {
+                    continue;
+                }
+                jps.find(&grid, begin, end).unwrap();
+                astar.find(&grid, begin, end).unwrap();
+                assert_eq!(jps.cost(), astar.cost(), "{:?} {:?}", begin, end);
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use crate::grid::Grid;
+use crate::pos::Pos;
+use crate::search::{DIAGDIR, RUSHDIR};
+
+// 连通区域的编号：0表示墙，其余每个区域一个编号。
+// 编辑一格时只重新标记受影响的区域，不用整张图重来
+impl Grid {
+    /// Label of the connected region `point` belongs to, following the
+    /// [`DiagonalMode`](crate::DiagonalMode). Two cells with the same label
+    /// can reach each other. `None` for walls and for anything outside the map.
+    ///
+    /// Labels are kept up to date as the grid is edited, but the label of a
+    /// given region may change after an edit.
+    pub fn component_of(&self, point: Pos) -> Option<u32> {
+        if !self.can_walk(point) {
+            return None;
+        }
+        return Some(self.labels[self.index(point)]);
+    }
+
+    /// Whether there is any path from `a` to `b`, without searching.
+    pub fn is_reachable(&self, a: Pos, b: Pos) -> bool {
+        return match (self.component_of(a), self.component_of(b)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        };
+    }
+
+    pub(crate) fn relabel_all(&mut self) {
+        let mut labels = std::mem::take(&mut self.labels);
+        labels.clear();
+        labels.resize(self.map.len(), 0);
+        self.next_label = 1;
+        for y in 0..self.size.y {
+            for x in 0..self.size.x {
+                let point = pos!(x, y);
+                if labels[self.index(point)] == 0 && self.can_walk(point) {
+                    let label = self.new_label();
+                    self.flood(&mut labels, point, 0, label);
+                }
+            }
+        }
+        self.labels = labels;
+    }
+
+    // point这一格能不能走刚刚变了。所有受它影响的一步都在它周围3x3之内：
+    // 斜走时擦过的两格正是这一步两端共同的邻居
+    pub(crate) fn relabel_around(&mut self, point: Pos) {
+        if self.next_label == u32::MAX {
+            return self.relabel_all();
+        }
+        let mut labels = std::mem::take(&mut self.labels);
+        let index = self.index(point);
+        if self.can_walk(point) {
+            // 新打通的格子把周围的区域连成一片；周围只有一个区域时直接并进去，
+            // 否则从它出发把其它区域都改成第一个区域的编号
+            let mut merged = None;
+            let mut single = true;
+            for dir in RUSHDIR.iter().chain(DIAGDIR.iter()) {
+                if self.can_step(point, *dir) {
+                    let label = labels[self.index(point + *dir)];
+                    match merged {
+                        None => merged = Some(label),
+                        Some(first) if first != label => single = false,
+                        _ => {}
+                    }
+                }
+            }
+            match merged {
+                None => labels[index] = self.new_label(),
+                Some(label) if single => labels[index] = label,
+                Some(label) => self.flood(&mut labels, point, u32::MAX, label),
+            }
+        } else {
+            // 原来的区域可能断开，每一块都至少有一格在它周围；
+            // 周围这几格绕过point还连在一起的话就不会断开
+            let old = labels[index];
+            labels[index] = 0;
+            if !self.ring_connected(&labels, point, old) {
+                for dir in RUSHDIR.iter().chain(DIAGDIR.iter()) {
+                    let next = point + *dir;
+                    if self.can_walk(next) && labels[self.index(next)] == old {
+                        let label = self.new_label();
+                        self.flood(&mut labels, next, old, label);
+                    }
+                }
+            }
+        }
+        self.labels = labels;
+    }
+
+    fn ring_connected(&self, labels: &[u32], point: Pos, old: u32) -> bool {
+        let ring = RUSHDIR
+            .iter()
+            .chain(DIAGDIR.iter())
+            .map(|&dir| point + dir)
+            .filter(|&pos| self.can_walk(pos) && labels[self.index(pos)] == old)
+            .collect::<Vec<_>>();
+        if ring.is_empty() {
+            return true;
+        }
+        let mut seen = vec![false; ring.len()];
+        let mut stack = vec![0];
+        seen[0] = true;
+        while let Some(i) = stack.pop() {
+            for j in 0..ring.len() {
+                let dir = ring[j] - ring[i];
+                if !seen[j] && dir.x.abs() <= 1 && dir.y.abs() <= 1 && self.can_step(ring[i], dir) {
+                    seen[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+        return seen.iter().all(|&seen| seen);
+    }
+
+    fn new_label(&mut self) -> u32 {
+        let label = self.next_label;
+        self.next_label += 1;
+        return label;
+    }
+
+    // 把从from能走到、编号为old的格子都改成label；old为u32::MAX时不看原编号
+    fn flood(&self, labels: &mut [u32], from: Pos, old: u32, label: u32) {
+        let mut stack = vec![from];
+        labels[self.index(from)] = label;
+        while let Some(pos) = stack.pop() {
+            for dir in RUSHDIR.iter().chain(DIAGDIR.iter()) {
+                let next = pos + *dir;
+                if !self.can_step(pos, *dir) {
+                    continue;
+                }
+                let index = self.index(next);
+                if labels[index] != label && (old == u32::MAX || labels[index] == old) {
+                    labels[index] = label;
+                    stack.push(next);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::search::JpsSearch;
+    use crate::testing::{random_edit, random_grid, rebuilt, Random, DIAGONAL_MODES};
+
+    // 编号本身可以不同，只要两边把格子分成同样的几块
+    fn same_regions(a: &Grid, b: &Grid) -> bool {
+        let (mut forward, mut backward) = (HashMap::new(), HashMap::new());
+        for y in 0..a.height() {
+            for x in 0..a.width() {
+                match (a.component_of(pos!(x, y)), b.component_of(pos!(x, y))) {
+                    (None, None) => {}
+                    (Some(la), Some(lb)) => {
+                        if *forward.entry(la).or_insert(lb) != lb
+                            || *backward.entry(lb).or_insert(la) != la
+                        {
+                            return false;
+                        }
+                    }
+                    _ => return false,
+                }
+            }
+        }
+        return true;
+    }
+
+    #[test]
+    fn edited_labels_match_a_fresh_grid() {
+        let mut random = Random::new(19);
+        for diagonal in DIAGONAL_MODES {
+            let mut grid = random_grid(&mut random, pos!(24, 20), 30, diagonal);
+            for _ in 0..300 {
+                random_edit(&mut random, &mut grid);
+                assert!(same_regions(&grid, &rebuilt(&grid)), "{:?}", diagonal);
+            }
+        }
+    }
+
+    #[test]
+    fn reachable_matches_a_full_search() {
+        let mut random = Random::new(119);
+        let mut search = JpsSearch::new();
+        for diagonal in DIAGONAL_MODES {
+            let mut grid = random_grid(&mut random, pos!(24, 20), 35, diagonal);
+            for _ in 0..40 {
+                for _ in 0..5 {
+                    random_edit(&mut random, &mut grid);
+                }
+                let goal = random.pos(&grid);
+                if !grid.can_walk(goal) {
+                    continue;
+                }
+                let field = search.flow_field(&grid, goal).unwrap();
+                for y in 0..grid.height() {
+                    for x in 0..grid.width() {
+                        let pos = pos!(x, y);
+                        let found = field.distance(pos).is_some();
+                        assert_eq!(grid.is_reachable(pos, goal), found, "{:?}", pos);
+                    }
+                }
+            }
+        }
+    }
+}
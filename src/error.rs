@@ -0,0 +1,33 @@
+use std::fmt;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// Why a query or map update was rejected. Thrown as-is to JS, where it can
+/// be compared against the exported `PathError` enum.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathError {
+    BadDimensions,
+    MapLengthMismatch,
+    OutOfBounds,
+    StartBlocked,
+    GoalBlocked,
+    BadCostModel,
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            PathError::BadDimensions => "map width and height must be positive",
+            PathError::MapLengthMismatch => "map length does not match width * height",
+            PathError::OutOfBounds => "position is outside the map",
+            PathError::StartBlocked => "start cell is blocked",
+            PathError::GoalBlocked => "goal cell is blocked",
+            PathError::BadCostModel => "step costs need 0 < straight <= diagonal < 2 * straight",
+        };
+        return f.write_str(msg);
+    }
+}
+
+impl std::error::Error for PathError {}
@@ -0,0 +1,86 @@
+use crate::pos::Pos;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// Cost to the goal and first step towards it for every cell of a grid,
+/// built by [`JpsSearch::flow_field`](crate::JpsSearch::flow_field).
+/// Units anywhere on the map can follow it without a search of their own.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct FlowField {
+    size: Pos,
+    goal: Pos,
+    distance: Vec<f64>,
+    direction: Vec<i8>,
+}
+
+impl FlowField {
+    pub(crate) fn new(size: Pos, goal: Pos, distance: &[f64], frompos: &[Pos]) -> Self {
+        let mut direction = vec![0; distance.len() * 2];
+        for (i, from) in frompos.iter().enumerate() {
+            if distance[i] != f64::INFINITY {
+                let here = pos!(i as i32 % size.x, i as i32 / size.x);
+                let dir = *from - here;
+                direction[i * 2] = dir.x as i8;
+                direction[i * 2 + 1] = dir.y as i8;
+            }
+        }
+        return Self {
+            size,
+            goal,
+            distance: distance.to_vec(),
+            direction,
+        };
+    }
+
+    pub fn width(&self) -> i32 {
+        return self.size.x;
+    }
+
+    pub fn height(&self) -> i32 {
+        return self.size.y;
+    }
+
+    pub fn goal(&self) -> Pos {
+        return self.goal;
+    }
+
+    fn index(&self, pos: Pos) -> Option<usize> {
+        if pos.x < 0 || pos.x >= self.size.x || pos.y < 0 || pos.y >= self.size.y {
+            return None;
+        }
+        return Some((pos.y * self.size.x + pos.x) as usize);
+    }
+
+    /// Cost from `pos` to the goal, `None` if it cannot reach it.
+    pub fn distance(&self, pos: Pos) -> Option<f64> {
+        let index = self.index(pos)?;
+        if self.distance[index] == f64::INFINITY {
+            return None;
+        }
+        return Some(self.distance[index]);
+    }
+
+    /// The step to take from `pos`, a unit vector that may be diagonal;
+    /// `(0, 0)` at the goal. `None` if `pos` cannot reach the goal.
+    pub fn direction(&self, pos: Pos) -> Option<Pos> {
+        self.distance(pos)?;
+        let index = self.index(pos)?;
+        return Some(pos!(
+            self.direction[index * 2] as i32,
+            self.direction[index * 2 + 1] as i32
+        ));
+    }
+
+    /// Costs row by row, `f64::INFINITY` where the goal cannot be reached.
+    pub fn distances(&self) -> &[f64] {
+        return &self.distance;
+    }
+
+    /// Steps row by row as `[dx, dy]` pairs, `[0, 0]` at the goal and where
+    /// it cannot be reached.
+    pub fn directions(&self) -> &[i8] {
+        return &self.direction;
+    }
+}
@@ -0,0 +1,453 @@
+use std::cmp;
+
+use crate::error::PathError;
+use crate::line::{trace_line, trace_segment};
+use crate::pos::{Point, Pos};
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// When a diagonal step between two cells is allowed, depending on the two
+/// cells it cuts past (the orthogonal neighbours shared by both ends).
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum DiagonalMode {
+    /// Only orthogonal steps.
+    Never,
+    /// Both cells cut past must be walkable: no cutting wall corners.
+    OnlyWhenNoObstacles,
+    /// At least one of the cells cut past must be walkable.
+    IfAtMostOneObstacle,
+    /// Diagonal steps are always allowed, even between two walls.
+    #[default]
+    Always,
+}
+
+/// Whether a line of sight that passes exactly through a grid corner is
+/// blocked by the walls touching that corner; see [`Grid::line_of_sight`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CornerRule {
+    /// The same as for a diagonal step under the grid's [`DiagonalMode`].
+    #[default]
+    FollowDiagonal,
+    /// Any wall touching the corner blocks the line.
+    Blocked,
+    /// Touching a corner never blocks; only the cells the line enters count.
+    Passable,
+}
+
+/// How the cell values of a [`Grid`] are read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Terrain {
+    /// `0` is walkable, anything else is a wall.
+    #[default]
+    Binary,
+    /// Cells equal to `blocked` are walls. Every other value is the cost of
+    /// entering that cell, with `0` counted as `1`.
+    Weighted { blocked: u8 },
+}
+
+/// An owned map of cells, by default `0` for walkable and anything else for a wall.
+/// See [`Terrain`] for per-cell costs.
+///
+/// The grid is meant to live as long as the level does: edit it in place with
+/// [`Grid::set_cell`], [`Grid::set_rect`] or [`Grid::load`] and keep querying it.
+/// The connected regions of the map are tracked along the way, see
+/// [`Grid::is_reachable`].
+#[derive(Clone, Debug)]
+pub struct Grid {
+    pub(crate) size: Pos,
+    pub(crate) map: Vec<u8>,
+    terrain: Terrain,
+    diagonal: DiagonalMode,
+    corners: CornerRule,
+    pub(crate) labels: Vec<u32>,
+    pub(crate) next_label: u32,
+    pub(crate) jumps: Vec<i32>,
+    pub(crate) rows: Vec<u64>,
+    pub(crate) cols: Vec<u64>,
+}
+
+impl Grid {
+    /// Wraps `map`, laid out row by row, as a `width` x `height` grid.
+    pub fn new(width: i32, height: i32, map: Vec<u8>) -> Result<Self, PathError> {
+        if width <= 0 || height <= 0 {
+            return Err(PathError::BadDimensions);
+        }
+        match width.checked_mul(height) {
+            Some(len) if len as usize == map.len() => {}
+            Some(_) => return Err(PathError::MapLengthMismatch),
+            None => return Err(PathError::BadDimensions),
+        }
+        let mut grid = Self {
+            size: pos!(width, height),
+            map,
+            terrain: Terrain::Binary,
+            diagonal: DiagonalMode::Always,
+            corners: CornerRule::FollowDiagonal,
+            labels: Vec::new(),
+            next_label: 1,
+            jumps: Vec::new(),
+            rows: Vec::new(),
+            cols: Vec::new(),
+        };
+        grid.relabel_all();
+        return Ok(grid);
+    }
+
+    /// Like [`Grid::new`], with the movement rules set up front, e.g.
+    /// [`DiagonalMode::Never`] for 4-connected levels.
+    pub fn with_diagonal(
+        width: i32,
+        height: i32,
+        map: Vec<u8>,
+        diagonal: DiagonalMode,
+    ) -> Result<Self, PathError> {
+        let mut grid = Self::new(width, height, map)?;
+        grid.set_diagonal(diagonal);
+        return Ok(grid);
+    }
+
+    pub fn diagonal(&self) -> DiagonalMode {
+        return self.diagonal;
+    }
+
+    pub fn set_diagonal(&mut self, diagonal: DiagonalMode) {
+        if diagonal != self.diagonal {
+            self.diagonal = diagonal;
+            self.relabel_all();
+            self.rebuild_jumps();
+        }
+    }
+
+    pub fn corner_rule(&self) -> CornerRule {
+        return self.corners;
+    }
+
+    /// Changes how [`Grid::line_of_sight`] treats lines through grid
+    /// corners, and with it any-angle searches and path smoothing.
+    pub fn set_corner_rule(&mut self, corners: CornerRule) {
+        self.corners = corners;
+    }
+
+    pub fn terrain(&self) -> Terrain {
+        return self.terrain;
+    }
+
+    /// Changes how the existing cell values are read; the values themselves are kept.
+    pub fn set_terrain(&mut self, terrain: Terrain) {
+        if terrain != self.terrain {
+            self.terrain = terrain;
+            self.rebuild_bits();
+            self.relabel_all();
+            self.rebuild_jumps();
+        }
+    }
+
+    pub fn is_weighted(&self) -> bool {
+        return self.terrain != Terrain::Binary;
+    }
+
+    pub fn width(&self) -> i32 {
+        return self.size.x;
+    }
+
+    pub fn height(&self) -> i32 {
+        return self.size.y;
+    }
+
+    /// The raw cell values, row by row.
+    pub fn cells(&self) -> &[u8] {
+        return &self.map;
+    }
+
+    pub(crate) fn index(&self, point: Pos) -> usize {
+        return (point.y * self.size.x + point.x) as usize;
+    }
+
+    pub fn contains(&self, point: Pos) -> bool {
+        return point.x >= 0 && point.x < self.size.x && point.y >= 0 && point.y < self.size.y;
+    }
+
+    /// `false` for walls and for anything outside the map.
+    pub fn can_walk(&self, point: Pos) -> bool {
+        if !self.contains(point) {
+            return false;
+        }
+        let value = self.map[self.index(point)];
+        return match self.terrain {
+            Terrain::Binary => value == 0,
+            Terrain::Weighted { blocked } => value != blocked,
+        };
+    }
+
+    /// Whether one step from `from` in `dir` (a unit vector, possibly
+    /// diagonal) lands on a walkable cell and is allowed by the [`DiagonalMode`].
+    pub fn can_step(&self, from: Pos, dir: Pos) -> bool {
+        if !self.can_walk(from + dir) {
+            return false;
+        }
+        if dir.x == 0 || dir.y == 0 {
+            return true;
+        }
+        return match self.diagonal {
+            DiagonalMode::Never => false,
+            DiagonalMode::OnlyWhenNoObstacles => {
+                self.can_walk(from + dir.xonly()) && self.can_walk(from + dir.yonly())
+            }
+            DiagonalMode::IfAtMostOneObstacle => {
+                self.can_walk(from + dir.xonly()) || self.can_walk(from + dir.yonly())
+            }
+            DiagonalMode::Always => true,
+        };
+    }
+
+    /// Cost of entering a walkable cell: always `1` on binary grids.
+    pub fn cost(&self, point: Pos) -> i32 {
+        return match self.terrain {
+            Terrain::Binary => 1,
+            Terrain::Weighted { .. } => cmp::max(self.map[self.index(point)], 1) as i32,
+        };
+    }
+
+    fn cell_value(&self, blocked: bool) -> u8 {
+        return match self.terrain {
+            Terrain::Binary => blocked as u8,
+            Terrain::Weighted { blocked: wall } => {
+                if blocked {
+                    wall
+                } else if wall == 0 {
+                    1
+                } else {
+                    0
+                }
+            }
+        };
+    }
+
+    /// Makes a cell a wall, or walkable. On weighted grids a walkable cell
+    /// gets the cheapest cost; use [`Grid::set_value`] for anything else.
+    pub fn set_cell(&mut self, point: Pos, blocked: bool) -> Result<(), PathError> {
+        let value = self.cell_value(blocked);
+        return self.set_value(point, value);
+    }
+
+    /// Writes a raw cell value, read according to the [`Terrain`].
+    pub fn set_value(&mut self, point: Pos, value: u8) -> Result<(), PathError> {
+        if !self.contains(point) {
+            return Err(PathError::OutOfBounds);
+        }
+        let walkable = self.can_walk(point);
+        let index = self.index(point);
+        self.map[index] = value;
+        if self.can_walk(point) != walkable {
+            self.update_bit(point);
+            self.relabel_around(point);
+            self.update_jumps(point, point);
+        }
+        return Ok(());
+    }
+
+    /// Fills the rectangle spanned by `a` and `b`, both corners included.
+    /// The part outside the map is ignored.
+    pub fn set_rect(&mut self, a: Pos, b: Pos, blocked: bool) {
+        let x0 = cmp::max(cmp::min(a.x, b.x), 0);
+        let x1 = cmp::min(cmp::max(a.x, b.x), self.size.x - 1);
+        let y0 = cmp::max(cmp::min(a.y, b.y), 0);
+        let y1 = cmp::min(cmp::max(a.y, b.y), self.size.y - 1);
+        let value = self.cell_value(blocked);
+        // 一格一格改，能不能走变了的格子像set_value一样只重新标记周围
+        let mut flipped = false;
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let point = pos!(x, y);
+                let walkable = self.can_walk(point);
+                let index = self.index(point);
+                self.map[index] = value;
+                if self.can_walk(point) != walkable {
+                    self.update_bit(point);
+                    self.relabel_around(point);
+                    flipped = true;
+                }
+            }
+        }
+        if flipped {
+            self.update_jumps(pos!(x0, y0), pos!(x1, y1));
+        }
+    }
+
+    /// Replaces every cell at once; `map` must have the same length as the grid.
+    pub fn load(&mut self, map: &[u8]) -> Result<(), PathError> {
+        if map.len() != self.map.len() {
+            return Err(PathError::MapLengthMismatch);
+        }
+        self.map.copy_from_slice(map);
+        self.rebuild_bits();
+        self.relabel_all();
+        self.rebuild_jumps();
+        return Ok(());
+    }
+
+    // 从origin开始、size大小的一块，单独当作一张地图；块外面都算墙
+    pub(crate) fn sub_grid(&self, origin: Pos, size: Pos) -> Grid {
+        let mut map = Vec::with_capacity((size.x * size.y) as usize);
+        for y in origin.y..origin.y + size.y {
+            let start = self.index(pos!(origin.x, y));
+            map.extend_from_slice(&self.map[start..start + size.x as usize]);
+        }
+        let mut grid = Self {
+            size,
+            map,
+            terrain: self.terrain,
+            diagonal: self.diagonal,
+            corners: self.corners,
+            labels: Vec::new(),
+            next_label: 1,
+            jumps: Vec::new(),
+            rows: Vec::new(),
+            cols: Vec::new(),
+        };
+        grid.relabel_all();
+        return grid;
+    }
+
+    pub(crate) fn check_endpoints(&self, begin: Pos, end: Pos) -> Result<(), PathError> {
+        if !self.contains(begin) || !self.contains(end) {
+            return Err(PathError::OutOfBounds);
+        }
+        if !self.can_walk(begin) {
+            return Err(PathError::StartBlocked);
+        }
+        if !self.can_walk(end) {
+            return Err(PathError::GoalBlocked);
+        }
+        return Ok(());
+    }
+
+    // 带权地图上，捷径不能穿过比两端更贵的格子
+    fn can_pass(&self, point: Pos, limit: i32) -> bool {
+        return self.can_walk(point) && self.cost(point) <= limit;
+    }
+
+    /// Whether a unit can move in a straight line from the centre of `a` to
+    /// the centre of `b`. Every cell the segment crosses must be walkable, and
+    /// where it passes exactly through a grid corner the [`CornerRule`]
+    /// decides. By default that follows the [`DiagonalMode`], as for a
+    /// diagonal step: touching a wall corner blocks the line under
+    /// [`DiagonalMode::OnlyWhenNoObstacles`] but not under
+    /// [`DiagonalMode::Always`]. With [`DiagonalMode::Never`] only horizontal
+    /// and vertical lines can be clear.
+    ///
+    /// On weighted grids the line may not cross a cell that costs more than
+    /// both ends.
+    pub fn line_of_sight(&self, a: Pos, b: Pos) -> bool {
+        let limit = if self.can_walk(a) && self.can_walk(b) {
+            cmp::min(self.cost(a), self.cost(b))
+        } else {
+            i32::MAX
+        };
+        let diff = b - a;
+        if self.diagonal == DiagonalMode::Never && diff.x != 0 && diff.y != 0 {
+            return false;
+        }
+        return trace_line(a, b, |cpos, dir| self.line_step(cpos, dir, b, limit));
+    }
+
+    /// [`Grid::line_of_sight`] between two points anywhere inside the map,
+    /// with the same rules. Both points must lie in walkable cells.
+    pub fn line_of_sight_points(&self, a: Point, b: Point) -> bool {
+        let (acell, bcell) = (a.cell(), b.cell());
+        if !self.can_walk(acell) || !self.can_walk(bcell) {
+            return false;
+        }
+        let limit = cmp::min(self.cost(acell), self.cost(bcell));
+        let diff = bcell - acell;
+        if self.diagonal == DiagonalMode::Never && diff.x != 0 && diff.y != 0 {
+            return false;
+        }
+        return trace_segment(a, b, |cpos, dir| self.line_step(cpos, dir, bcell, limit));
+    }
+
+    fn line_step(&self, cpos: Pos, dir: Pos, end: Pos, limit: i32) -> bool {
+        let next = cpos + dir;
+        if dir.x != 0 && dir.y != 0 {
+            // 正好穿过格点，看碰到角的两格
+            let touched = [cpos + dir.xonly(), cpos + dir.yonly()];
+            let clear = match self.corners {
+                CornerRule::FollowDiagonal => self.can_step(cpos, dir),
+                CornerRule::Blocked => {
+                    self.can_walk(next) && touched.iter().all(|&p| self.can_walk(p))
+                }
+                CornerRule::Passable => self.can_walk(next),
+            };
+            if !clear {
+                return false;
+            }
+        } else if !self.can_walk(next) {
+            return false;
+        }
+        return next == end || self.can_pass(next, limit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corner_rule_decides_lines_through_corners() {
+        // 对角线正好擦过(1, 0)这堵墙的角
+        #[rustfmt::skip]
+        let map = vec![
+            0, 1, 0,
+            0, 0, 0,
+            0, 0, 0,
+        ];
+        let (a, b) = (pos!(0, 0), pos!(2, 2));
+        let cases = [
+            (DiagonalMode::Always, CornerRule::FollowDiagonal, true),
+            (DiagonalMode::Always, CornerRule::Blocked, false),
+            (DiagonalMode::Always, CornerRule::Passable, true),
+            (
+                DiagonalMode::OnlyWhenNoObstacles,
+                CornerRule::FollowDiagonal,
+                false,
+            ),
+            (
+                DiagonalMode::OnlyWhenNoObstacles,
+                CornerRule::Blocked,
+                false,
+            ),
+            (
+                DiagonalMode::OnlyWhenNoObstacles,
+                CornerRule::Passable,
+                true,
+            ),
+        ];
+        for (diagonal, corners, clear) in cases {
+            let mut grid = Grid::with_diagonal(3, 3, map.clone(), diagonal).unwrap();
+            grid.set_corner_rule(corners);
+            assert_eq!(
+                grid.line_of_sight(a, b),
+                clear,
+                "{:?} {:?}",
+                diagonal,
+                corners
+            );
+            let (pa, pb) = (Point::center(a), Point::center(b));
+            assert_eq!(grid.line_of_sight_points(pa, pb), clear);
+        }
+
+        // 线真正穿过的格子是墙时，什么规则都挡住
+        let mut grid = Grid::new(3, 3, vec![0, 0, 0, 0, 1, 0, 0, 0, 0]).unwrap();
+        for corners in [
+            CornerRule::FollowDiagonal,
+            CornerRule::Blocked,
+            CornerRule::Passable,
+        ] {
+            grid.set_corner_rule(corners);
+            assert!(!grid.line_of_sight(a, b), "{:?}", corners);
+        }
+    }
+}
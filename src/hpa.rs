@@ -0,0 +1,546 @@
+use std::cmp;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::error::PathError;
+use crate::grid::Grid;
+use crate::pos::Pos;
+use crate::search::{CostModel, JpsSearch, Pointinfo, TieBreak, RUSHDIR, UNREACHED};
+
+// 边界上连续的一段通道不短于这个长度时，两头各放一个入口，否则只在中间放一个
+const ENTRANCE_SPLIT: i32 = 6;
+
+#[derive(Clone, Debug)]
+struct Cluster {
+    origin: Pos,
+    grid: Grid,
+    // 入口所在的格子，用整张地图的坐标
+    nodes: Vec<Pos>,
+    // costs[i * nodes.len() + j]是块内从nodes[i]走到nodes[j]的代价，和搜索里一样是整数，
+    // 走不到时为UNREACHED
+    costs: Vec<i64>,
+}
+
+/// Hierarchical pathfinding (HPA*) over a [`Grid`], for large maps.
+///
+/// The map is cut into square clusters. Wherever two neighbouring clusters
+/// are joined by open cells, entrance nodes are placed on both sides, and
+/// the cost between the entrances of each cluster is computed ahead of time
+/// with [`JpsSearch`]. A query then searches the small graph of entrances and
+/// only runs cell-level searches inside the clusters it passes through.
+///
+/// Paths are optimal within each cluster but may be slightly longer than
+/// the ones [`JpsSearch::find`] returns. Only straight steps are considered
+/// across cluster borders; when that leaves the goal out of reach although
+/// [`Grid::is_reachable`] says otherwise, the query falls back to a full
+/// search.
+///
+/// The hierarchy does not watch the grid: call [`Hierarchy::update`] after
+/// editing cells and [`Hierarchy::rebuild`] after anything else, such as a
+/// new [`DiagonalMode`](crate::DiagonalMode) or [`Terrain`](crate::Terrain).
+#[derive(Debug)]
+pub struct Hierarchy {
+    cluster_size: i32,
+    count: Pos,
+    clusters: Vec<Cluster>,
+    search: JpsSearch,
+    cost: Option<i64>,
+    expanded: usize,
+}
+
+// 把segment接到path后面，segment的起点就是path的终点；去掉共线的中间点
+fn append(path: &mut Vec<Pos>, segment: &[Pos]) {
+    for &pos in segment {
+        if path.last() == Some(&pos) {
+            continue;
+        }
+        if path.len() >= 2 {
+            let a = path[path.len() - 2];
+            let b = path[path.len() - 1];
+            let (d1, d2) = (b - a, pos - b);
+            if d1.cross(d2) == 0 && d1.dot(d2) > 0 {
+                path.pop();
+            }
+        }
+        path.push(pos);
+    }
+}
+
+impl Hierarchy {
+    /// Builds the hierarchy for `grid` with clusters of `cluster_size` x
+    /// `cluster_size` cells, using the default [`CostModel`].
+    pub fn new(grid: &Grid, cluster_size: i32) -> Result<Self, PathError> {
+        return Self::with_cost_model(grid, cluster_size, CostModel::default());
+    }
+
+    /// Like [`Hierarchy::new`], with the step costs used for the precomputed
+    /// costs and the queries.
+    pub fn with_cost_model(
+        grid: &Grid,
+        cluster_size: i32,
+        costs: CostModel,
+    ) -> Result<Self, PathError> {
+        if cluster_size <= 0 {
+            return Err(PathError::BadDimensions);
+        }
+        let mut search = JpsSearch::new();
+        search.set_cost_model(costs)?;
+        let mut hierarchy = Self {
+            cluster_size,
+            count: pos!(0, 0),
+            clusters: Vec::new(),
+            search,
+            cost: None,
+            expanded: 0,
+        };
+        hierarchy.rebuild(grid);
+        return Ok(hierarchy);
+    }
+
+    pub fn cluster_size(&self) -> i32 {
+        return self.cluster_size;
+    }
+
+    pub fn cost_model(&self) -> CostModel {
+        return self.search.cost_model();
+    }
+
+    pub fn tie_break(&self) -> TieBreak {
+        return self.search.tie_break();
+    }
+
+    /// Tie-break policy of the queries, over the abstract graph and inside
+    /// the clusters; see [`TieBreak`].
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.search.set_tie_break(tie_break);
+    }
+
+    /// Number of entrance nodes in the abstract graph.
+    pub fn node_count(&self) -> usize {
+        return self
+            .clusters
+            .iter()
+            .map(|cluster| cluster.nodes.len())
+            .sum();
+    }
+
+    /// Rebuilds every cluster from `grid`, which may have been replaced
+    /// by one of another size.
+    pub fn rebuild(&mut self, grid: &Grid) {
+        let size = self.cluster_size;
+        self.count = pos!(
+            (grid.width() + size - 1) / size,
+            (grid.height() + size - 1) / size
+        );
+        self.clusters.clear();
+        for cy in 0..self.count.y {
+            for cx in 0..self.count.x {
+                let cluster = self.build_cluster(grid, pos!(cx, cy));
+                self.clusters.push(cluster);
+            }
+        }
+    }
+
+    /// Rebuilds the clusters affected by edits to the rectangle spanned by
+    /// `a` and `b`, both corners included, e.g. after [`Grid::set_rect`].
+    /// Clusters across a border the rectangle touches are rebuilt too, since
+    /// their entrances may have moved.
+    pub fn update(&mut self, grid: &Grid, a: Pos, b: Pos) {
+        let size = self.cluster_size;
+        // 先像Grid::set_rect一样截到地图里，再往外扩一格，坐标再大也不会溢出
+        let x0 = cmp::max(cmp::min(a.x, b.x), 0);
+        let x1 = cmp::min(cmp::max(a.x, b.x), grid.width() - 1);
+        let y0 = cmp::max(cmp::min(a.y, b.y), 0);
+        let y1 = cmp::min(cmp::max(a.y, b.y), grid.height() - 1);
+        if x0 > x1 || y0 > y1 {
+            return;
+        }
+        let x0 = cmp::max(x0 - 1, 0) / size;
+        let x1 = cmp::min(x1 + 1, grid.width() - 1) / size;
+        let y0 = cmp::max(y0 - 1, 0) / size;
+        let y1 = cmp::min(y1 + 1, grid.height() - 1) / size;
+        for cy in y0..=y1 {
+            for cx in x0..=x1 {
+                let index = (cy * self.count.x + cx) as usize;
+                self.clusters[index] = self.build_cluster(grid, pos!(cx, cy));
+            }
+        }
+    }
+
+    fn cluster_of(&self, pos: Pos) -> usize {
+        let size = self.cluster_size;
+        return ((pos.y / size) * self.count.x + pos.x / size) as usize;
+    }
+
+    fn build_cluster(&mut self, grid: &Grid, at: Pos) -> Cluster {
+        let size = self.cluster_size;
+        let origin = pos!(at.x * size, at.y * size);
+        let extent = pos!(
+            cmp::min(size, grid.width() - origin.x),
+            cmp::min(size, grid.height() - origin.y)
+        );
+        let mut nodes = Vec::new();
+        let last = origin + extent - pos!(1, 1);
+        // 四条边，每条边给出本块这一侧的第一格、沿边的方向和指向邻块的方向
+        let sides = [
+            (at.x > 0, origin, pos!(0, 1), pos!(-1, 0), extent.y),
+            (
+                at.x + 1 < self.count.x,
+                pos!(last.x, origin.y),
+                pos!(0, 1),
+                pos!(1, 0),
+                extent.y,
+            ),
+            (at.y > 0, origin, pos!(1, 0), pos!(0, -1), extent.x),
+            (
+                at.y + 1 < self.count.y,
+                pos!(origin.x, last.y),
+                pos!(1, 0),
+                pos!(0, 1),
+                extent.x,
+            ),
+        ];
+        for (exists, start, along, out, len) in sides {
+            if !exists {
+                continue;
+            }
+            let open = |i: i32| {
+                let pos = start + pos!(along.x * i, along.y * i);
+                return grid.can_walk(pos) && grid.can_walk(pos + out);
+            };
+            let mut i = 0;
+            while i < len {
+                if !open(i) {
+                    i += 1;
+                    continue;
+                }
+                let begin = i;
+                while i < len && open(i) {
+                    i += 1;
+                }
+                // 两侧块各自算一遍，取法相同，入口就能对上
+                let picks = if i - begin < ENTRANCE_SPLIT {
+                    vec![begin + (i - begin) / 2]
+                } else {
+                    vec![begin, i - 1]
+                };
+                for pick in picks {
+                    let pos = start + pos!(along.x * pick, along.y * pick);
+                    if !nodes.contains(&pos) {
+                        nodes.push(pos);
+                    }
+                }
+            }
+        }
+
+        let local = grid.sub_grid(origin, extent);
+        let locals = nodes.iter().map(|&pos| pos - origin).collect::<Vec<_>>();
+        let k = nodes.len();
+        let mut costs = vec![UNREACHED; k * k];
+        for j in 0..k {
+            // 以每个入口为终点搜一次，同时得到所有入口到它的代价
+            if self.search.find_many(&local, &locals, locals[j]).is_err() {
+                continue;
+            }
+            for i in 0..k {
+                if let Some(cost) = self.search.cost_units_to(&local, locals[i]) {
+                    costs[i * k + j] = cost;
+                }
+            }
+        }
+
+        return Cluster {
+            origin,
+            grid: local,
+            nodes,
+            costs,
+        };
+    }
+
+    // 块内从a到b的路径，用整张地图的坐标
+    fn local_path(&mut self, c: usize, a: Pos, b: Pos) -> Vec<Pos> {
+        let cluster = &self.clusters[c];
+        let origin = cluster.origin;
+        return match self.search.find(&cluster.grid, a - origin, b - origin) {
+            Ok(path) => path.into_iter().map(|pos| pos + origin).collect(),
+            Err(_) => Vec::new(),
+        };
+    }
+
+    fn local_cost(&mut self, c: usize, a: Pos, b: Pos) -> Option<i64> {
+        self.local_path(c, a, b);
+        return self.search.cost_units();
+    }
+
+    // 终点所在块的每个入口走到终点的代价，一次搜完
+    fn exit_costs(&mut self, c: usize, end: Pos) -> Vec<Option<i64>> {
+        let cluster = &self.clusters[c];
+        let origin = cluster.origin;
+        let locals = cluster
+            .nodes
+            .iter()
+            .map(|&pos| pos - origin)
+            .collect::<Vec<_>>();
+        if self
+            .search
+            .find_many(&cluster.grid, &locals, end - origin)
+            .is_err()
+        {
+            return vec![None; locals.len()];
+        }
+        return locals
+            .iter()
+            .map(|&pos| self.search.cost_units_to(&cluster.grid, pos))
+            .collect();
+    }
+
+    /// Searches for a path from `begin` to `end` over the abstract graph,
+    /// then refines it into cells. The path has the same form as the one
+    /// [`JpsSearch::find`] returns.
+    pub fn find(&mut self, grid: &Grid, begin: Pos, end: Pos) -> Result<Vec<Pos>, PathError> {
+        grid.check_endpoints(begin, end)?;
+        self.cost = None;
+        self.expanded = 0;
+        if !grid.is_reachable(begin, end) {
+            return Ok(Vec::new());
+        }
+
+        let (cb, ce) = (self.cluster_of(begin), self.cluster_of(end));
+        // 每个入口：(代价, 从哪个入口来, 是否已关闭)，None表示直接从起点来
+        let mut nodes: HashMap<Pos, (i64, Option<Pos>, bool)> = HashMap::new();
+        let mut openlist = BinaryHeap::new();
+        for i in 0..self.clusters[cb].nodes.len() {
+            let node = self.clusters[cb].nodes[i];
+            if let Some(cost) = self.local_cost(cb, begin, node) {
+                nodes.insert(node, (cost, None, false));
+                openlist.push(Pointinfo {
+                    position: node,
+                    distance: cost,
+                    dist_gh: cost.saturating_add(self.search.hfunc(grid, node, end)),
+                    tie: self.search.tie_break(),
+                });
+            }
+        }
+        let exits = self.exit_costs(ce, end);
+        let mut best = (UNREACHED, None);
+        if cb == ce {
+            if let Some(cost) = self.local_cost(cb, begin, end) {
+                best = (cost, None);
+            }
+        }
+
+        while let Some(pinfo) = openlist.pop() {
+            // 估价是下界，再往下搜不会比已找到的更好
+            if pinfo.dist_gh >= best.0 {
+                break;
+            }
+            let pos = pinfo.position;
+            let entry = nodes.get_mut(&pos).unwrap();
+            if entry.2 || entry.0 < pinfo.distance {
+                continue;
+            }
+            entry.2 = true;
+            self.expanded += 1;
+
+            let dist = pinfo.distance;
+            let c = self.cluster_of(pos);
+            let cluster = &self.clusters[c];
+            let k = cluster.nodes.len();
+            let i = cluster.nodes.iter().position(|&node| node == pos).unwrap();
+            if c == ce {
+                if let Some(exit) = exits[i] {
+                    let total = dist.saturating_add(exit);
+                    if total < best.0 {
+                        best = (total, Some(pos));
+                    }
+                }
+            }
+
+            let mut nexts = Vec::new();
+            for j in 0..k {
+                if j != i && cluster.costs[i * k + j] != UNREACHED {
+                    let cost = dist.saturating_add(cluster.costs[i * k + j]);
+                    nexts.push((cluster.nodes[j], cost));
+                }
+            }
+            // 跨过边界只走一步，进入邻块的那一格
+            for dir in RUSHDIR {
+                let next = pos + dir;
+                if grid.contains(next) && self.cluster_of(next) != c {
+                    let other = &self.clusters[self.cluster_of(next)];
+                    if other.nodes.contains(&next) {
+                        let step = self
+                            .search
+                            .straight_units()
+                            .saturating_mul(grid.cost(next) as i64);
+                        nexts.push((next, dist.saturating_add(step)));
+                    }
+                }
+            }
+
+            for (next, dist) in nexts {
+                let entry = nodes.entry(next).or_insert((UNREACHED, None, false));
+                if !entry.2 && dist < entry.0 {
+                    *entry = (dist, Some(pos), false);
+                    openlist.push(Pointinfo {
+                        position: next,
+                        distance: dist,
+                        dist_gh: dist.saturating_add(self.search.hfunc(grid, next, end)),
+                        tie: self.search.tie_break(),
+                    });
+                }
+            }
+        }
+
+        if best.0 == UNREACHED {
+            let path = self.search.find(grid, begin, end)?;
+            self.cost = self.search.cost_units();
+            return Ok(path);
+        }
+
+        let mut chain = Vec::new();
+        let mut node = best.1;
+        while let Some(pos) = node {
+            chain.push(pos);
+            node = nodes[&pos].1;
+        }
+        chain.push(begin);
+        chain.reverse();
+        chain.push(end);
+
+        let mut path = vec![begin];
+        for pair in chain.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let c = self.cluster_of(a);
+            if c == self.cluster_of(b) {
+                let segment = self.local_path(c, a, b);
+                append(&mut path, &segment);
+            } else {
+                append(&mut path, &[a, b]);
+            }
+        }
+        self.cost = Some(best.0);
+
+        return Ok(path);
+    }
+
+    /// Cost of the path found by the last [`Hierarchy::find`], as
+    /// [`JpsSearch::cost`] would report it. `None` if no path was found.
+    pub fn cost(&self) -> Option<f64> {
+        return self.cost.map(|cost| self.search.to_cost(cost));
+    }
+
+    /// Number of entrance nodes expanded by the last [`Hierarchy::find`].
+    pub fn nodes_expanded(&self) -> usize {
+        return self.expanded;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{random_edit, random_grid, rebuilt, Random, DIAGONAL_MODES};
+
+    // 一步一步沿着路径走，每步都要走得通，返回按默认代价模型算的代价
+    fn walk(grid: &Grid, path: &[Pos]) -> f64 {
+        let costs = CostModel::default();
+        let mut cost = 0.0;
+        for pair in path.windows(2) {
+            let diff = pair[1] - pair[0];
+            assert!(diff.x == 0 || diff.y == 0 || diff.x.abs() == diff.y.abs());
+            let dir = diff.signxy();
+            let step = if dir.x != 0 && dir.y != 0 {
+                costs.diagonal()
+            } else {
+                costs.straight()
+            };
+            let mut pos = pair[0];
+            while pos != pair[1] {
+                assert!(grid.can_step(pos, dir), "{:?} -> {:?}", pos, pos + dir);
+                pos = pos + dir;
+                cost += step * grid.cost(pos) as f64;
+            }
+        }
+        return cost;
+    }
+
+    #[test]
+    fn update_clamps_huge_rectangles() {
+        let mut grid = Grid::new(16, 16, vec![0; 256]).unwrap();
+        let mut hierarchy = Hierarchy::new(&grid, 4).unwrap();
+        // 第7行只在最左边留一个口子
+        let (a, b) = (pos!(1, 7), pos!(i32::MAX, 7));
+        grid.set_rect(a, b, true);
+        hierarchy.update(&grid, a, b);
+
+        let (begin, end) = (pos!(15, 0), pos!(15, 15));
+        let mut search = JpsSearch::new();
+        search.find(&grid, begin, end).unwrap();
+        assert_eq!(search.cost(), Some(75.0));
+        let path = hierarchy.find(&grid, begin, end).unwrap();
+        let mut fresh = Hierarchy::new(&grid, 4).unwrap();
+        assert_eq!(path, fresh.find(&grid, begin, end).unwrap());
+        assert_eq!(hierarchy.cost(), fresh.cost());
+        assert_eq!(hierarchy.cost(), Some(walk(&grid, &path)));
+        assert!(hierarchy.cost().unwrap() >= 75.0);
+
+        // 整个在地图外面的矩形什么都不用重建
+        hierarchy.update(&grid, pos!(i32::MIN, -5), pos!(-1, i32::MIN));
+        assert_eq!(hierarchy.find(&grid, begin, end).unwrap(), path);
+    }
+
+    #[test]
+    fn paths_are_walkable_and_never_shorter_than_jps() {
+        let mut random = Random::new(20);
+        let mut search = JpsSearch::new();
+        for diagonal in DIAGONAL_MODES {
+            for cluster_size in [5, 8] {
+                let grid = random_grid(&mut random, pos!(48, 40), 25, diagonal);
+                let mut hierarchy = Hierarchy::new(&grid, cluster_size).unwrap();
+                for _ in 0..200 {
+                    let (begin, end) = (random.pos(&grid), random.pos(&grid));
+                    if !grid.can_walk(begin) || !grid.can_walk(end) {
+                        continue;
+                    }
+                    let path = hierarchy.find(&grid, begin, end).unwrap();
+                    let shortest = search.find(&grid, begin, end).unwrap();
+                    assert_eq!(path.is_empty(), shortest.is_empty());
+                    if path.is_empty() {
+                        assert_eq!(hierarchy.cost(), None);
+                        continue;
+                    }
+                    assert_eq!((path[0], path[path.len() - 1]), (begin, end));
+                    assert_eq!(hierarchy.cost(), Some(walk(&grid, &path)));
+                    assert!(hierarchy.cost() >= search.cost());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn updated_hierarchy_matches_a_fresh_one() {
+        let mut random = Random::new(21);
+        for diagonal in DIAGONAL_MODES {
+            let mut grid = random_grid(&mut random, pos!(40, 36), 25, diagonal);
+            let mut hierarchy = Hierarchy::new(&grid, 6).unwrap();
+            for _ in 0..30 {
+                for _ in 0..5 {
+                    let (a, b) = random_edit(&mut random, &mut grid);
+                    hierarchy.update(&grid, a, b);
+                }
+                let fresh_grid = rebuilt(&grid);
+                let mut fresh = Hierarchy::new(&fresh_grid, 6).unwrap();
+                for _ in 0..20 {
+                    let (begin, end) = (random.pos(&grid), random.pos(&grid));
+                    if !grid.can_walk(begin) || !grid.can_walk(end) {
+                        continue;
+                    }
+                    assert_eq!(
+                        hierarchy.find(&grid, begin, end).unwrap(),
+                        fresh.find(&fresh_grid, begin, end).unwrap()
+                    );
+                    assert_eq!(hierarchy.cost(), fresh.cost());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use crate::grid::{DiagonalMode, Grid};
+use crate::pos::Pos;
+use crate::search::{diag_forced, straight_forced, DIAGDIR, RUSHDIR};
+
+// JPS+的跳跃距离表：每格每个方向一个数。正数k表示往这个方向第k格是跳点，
+// 0或负数-k表示在撞墙前只能走k格、路上没有跳点。
+// 只和哪些格子能走有关，编辑时只重算受影响的行、列和斜线
+const DIRS: [Pos; 8] = [
+    RUSHDIR[0], RUSHDIR[1], RUSHDIR[2], RUSHDIR[3], DIAGDIR[0], DIAGDIR[1], DIAGDIR[2], DIAGDIR[3],
+];
+
+fn dir_index(dir: Pos) -> usize {
+    return DIRS.iter().position(|&d| d == dir).unwrap();
+}
+
+impl Grid {
+    pub fn has_jump_table(&self) -> bool {
+        return !self.jumps.is_empty();
+    }
+
+    /// Builds or drops the jump distance table used by
+    /// [`SearchMode::JpsPlus`](crate::SearchMode::JpsPlus): for every cell
+    /// and direction, how far the next jump point or wall is. It costs 32
+    /// bytes per cell and is kept up to date as the grid is edited.
+    pub fn set_jump_table(&mut self, enabled: bool) {
+        self.jumps = Vec::new();
+        if enabled {
+            self.jumps.resize(self.map.len() * DIRS.len(), 0);
+            self.rebuild_jumps();
+        }
+    }
+
+    // 往dir走的跳跃距离，见上面的说明
+    pub(crate) fn jump_distance(&self, point: Pos, dir: Pos) -> i32 {
+        return self.jump_distance_in(&self.jumps, point, dir);
+    }
+
+    fn jump_distance_in(&self, jumps: &[i32], point: Pos, dir: Pos) -> i32 {
+        return jumps[self.index(point) * DIRS.len() + dir_index(dir)];
+    }
+
+    // 这一格往dir方向有跳点
+    fn jump_ahead(&self, jumps: &[i32], point: Pos, dir: Pos) -> bool {
+        return self.jump_distance_in(jumps, point, dir) > 0;
+    }
+
+    // 沿dir走进point这一格时它是不是跳点，斜线要用到已经算好的直线结果
+    fn is_jump_point(&self, jumps: &[i32], point: Pos, dir: Pos) -> bool {
+        if dir.x != 0 && dir.y != 0 {
+            return diag_forced(self, point, dir)
+                || self.jump_ahead(jumps, point, dir.xonly())
+                || self.jump_ahead(jumps, point, dir.yonly());
+        }
+        // 只能横竖走时，竖着走到横向有跳点的地方也要停下
+        return straight_forced(self, point, dir)
+            || (self.diagonal() == DiagonalMode::Never
+                && dir.x == 0
+                && (self.jump_ahead(jumps, point, pos!(1, 0))
+                    || self.jump_ahead(jumps, point, pos!(-1, 0))));
+    }
+
+    // pos往dir的跳跃距离，ahead是下一格往dir的跳跃距离
+    fn jump_value(&self, jumps: &[i32], pos: Pos, dir: Pos, ahead: i32) -> i32 {
+        let next = pos + dir;
+        return if !self.contains(next) || !self.can_step(pos, dir) {
+            0
+        } else if self.is_jump_point(jumps, next, dir) {
+            1
+        } else if ahead > 0 {
+            ahead + 1
+        } else {
+            ahead - 1
+        };
+    }
+
+    fn store_jump(
+        &self,
+        jumps: &mut [i32],
+        pos: Pos,
+        dir: Pos,
+        value: i32,
+        changed: &mut Vec<Pos>,
+    ) {
+        let index = self.index(pos) * DIRS.len() + dir_index(dir);
+        if (jumps[index] > 0) != (value > 0) {
+            changed.push(pos);
+        }
+        jumps[index] = value;
+    }
+
+    // 重算through所在的整条dir方向的线，从线的尽头往回推；
+    // 前方有没有跳点变了的格子记到changed里
+    fn jump_line(&self, jumps: &mut [i32], through: Pos, dir: Pos, changed: &mut Vec<Pos>) {
+        let mut pos = through;
+        while self.contains(pos + dir) {
+            pos = pos + dir;
+        }
+        let mut ahead = 0;
+        while self.contains(pos) {
+            let value = self.jump_value(jumps, pos, dir, ahead);
+            self.store_jump(jumps, pos, dir, value, changed);
+            ahead = value;
+            pos = pos - dir;
+        }
+    }
+
+    // 从from开始往回重算，算出来和原来一样就可以停了：后面的格子只看前一格
+    fn jump_back(&self, jumps: &mut [i32], from: Pos, dir: Pos, changed: &mut Vec<Pos>) {
+        let mut pos = from;
+        while self.contains(pos) {
+            let next = pos + dir;
+            let ahead = if self.contains(next) {
+                self.jump_distance_in(jumps, next, dir)
+            } else {
+                0
+            };
+            let value = self.jump_value(jumps, pos, dir, ahead);
+            if value == self.jump_distance_in(jumps, pos, dir) {
+                break;
+            }
+            self.store_jump(jumps, pos, dir, value, changed);
+            pos = pos - dir;
+        }
+    }
+
+    pub(crate) fn rebuild_jumps(&mut self) {
+        if self.jumps.is_empty() {
+            return;
+        }
+        let mut jumps = std::mem::take(&mut self.jumps);
+        let mut changed = Vec::new();
+        // 横着的先算，竖着的和斜着的都要用到
+        let dirs = [pos!(1, 0), pos!(-1, 0), pos!(0, 1), pos!(0, -1)];
+        for dir in dirs.iter().chain(DIAGDIR.iter()) {
+            if dir.x != 0 && dir.y != 0 && self.diagonal() == DiagonalMode::Never {
+                continue;
+            }
+            for y in 0..self.size.y {
+                for x in 0..self.size.x {
+                    if !self.contains(pos!(x, y) - *dir) {
+                        self.jump_line(&mut jumps, pos!(x, y), *dir, &mut changed);
+                        changed.clear();
+                    }
+                }
+            }
+        }
+        self.jumps = jumps;
+    }
+
+    // lo到hi这一块（含两角）里有格子能不能走变了：这一块周围多一圈的
+    // 行和列整条重算；前方有没有跳点变了的格子，它身后的斜线
+    //（只能横竖走时是竖线）往回重算
+    pub(crate) fn update_jumps(&mut self, lo: Pos, hi: Pos) {
+        if self.jumps.is_empty() {
+            return;
+        }
+        let mut jumps = std::mem::take(&mut self.jumps);
+        let never = self.diagonal() == DiagonalMode::Never;
+
+        let mut changed = Vec::new();
+        for y in lo.y - 1..=hi.y + 1 {
+            if y >= 0 && y < self.size.y {
+                self.jump_line(&mut jumps, pos!(0, y), pos!(1, 0), &mut changed);
+                self.jump_line(&mut jumps, pos!(0, y), pos!(-1, 0), &mut changed);
+            }
+        }
+        let mut verticals = Vec::new();
+        if never {
+            for &pos in &changed {
+                for dir in [pos!(0, 1), pos!(0, -1)] {
+                    self.jump_back(&mut jumps, pos - dir, dir, &mut verticals);
+                }
+            }
+        }
+        for x in lo.x - 1..=hi.x + 1 {
+            if x >= 0 && x < self.size.x {
+                self.jump_line(&mut jumps, pos!(x, 0), pos!(0, 1), &mut changed);
+                self.jump_line(&mut jumps, pos!(x, 0), pos!(0, -1), &mut changed);
+            }
+        }
+
+        if !never {
+            for y in lo.y - 1..=hi.y + 1 {
+                for x in lo.x - 1..=hi.x + 1 {
+                    changed.push(pos!(x, y));
+                }
+            }
+            let mut discard = Vec::new();
+            for &pos in &changed {
+                for dir in DIAGDIR {
+                    self.jump_back(&mut jumps, pos, dir, &mut discard);
+                    self.jump_back(&mut jumps, pos - dir, dir, &mut discard);
+                }
+            }
+        }
+        self.jumps = jumps;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{JpsSearch, SearchMode};
+    use crate::testing::{random_edit, random_grid, rebuilt, Random, DIAGONAL_MODES};
+
+    #[test]
+    fn edited_table_matches_a_fresh_grid() {
+        let mut random = Random::new(21);
+        for diagonal in DIAGONAL_MODES {
+            let mut grid = random_grid(&mut random, pos!(24, 20), 30, diagonal);
+            grid.set_jump_table(true);
+            for _ in 0..300 {
+                random_edit(&mut random, &mut grid);
+                assert!(grid.jumps == rebuilt(&grid).jumps, "{:?}", diagonal);
+            }
+        }
+    }
+
+    #[test]
+    fn jps_plus_costs_match_astar() {
+        let mut random = Random::new(121);
+        let mut plus = JpsSearch::new();
+        plus.set_mode(SearchMode::JpsPlus);
+        let mut astar = JpsSearch::new();
+        astar.set_mode(SearchMode::AStar);
+        for diagonal in DIAGONAL_MODES {
+            let mut grid = random_grid(&mut random, pos!(40, 30), 25, diagonal);
+            grid.set_jump_table(true);
+            for _ in 0..100 {
+                random_edit(&mut random, &mut grid);
+                let (begin, end) = (random.pos(&grid), random.pos(&grid));
+                if !grid.can_walk(begin) || !grid.can_walk(end) {
+                    continue;
+                }
+                plus.find(&grid, begin, end).unwrap();
+                astar.find(&grid, begin, end).unwrap();
+                assert_eq!(plus.cost(), astar.cost(), "{:?} {:?}", begin, end);
+            }
+        }
+    }
+}
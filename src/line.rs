@@ -0,0 +1,92 @@
+use crate::pos::{Point, Pos};
+
+// 整数的supercover直线，从a格中心连到b格中心，依次给出每一步
+// visit(cpos, dir)：从cpos走到cpos + dir；正好穿过格点时dir是斜的，
+// 这时两侧的格子只被碰到一个角
+pub(crate) fn trace_line(a: Pos, b: Pos, mut visit: impl FnMut(Pos, Pos) -> bool) -> bool {
+    let diff = b - a;
+    let sign = diff.signxy();
+    let (dx, dy) = (diff.x.abs() as i64, diff.y.abs() as i64);
+    let (mut ix, mut iy) = (0i64, 0i64);
+    let mut cpos = a;
+    while ix < dx || iy < dy {
+        // 比较下一次穿过竖线和横线的位置：(2ix+1)/2dx 和 (2iy+1)/2dy
+        let decision = (2 * ix + 1) * dy - (2 * iy + 1) * dx;
+        let dir = if decision == 0 {
+            ix += 1;
+            iy += 1;
+            sign
+        } else if decision < 0 {
+            ix += 1;
+            sign.xonly()
+        } else {
+            iy += 1;
+            sign.yonly()
+        };
+        if !visit(cpos, dir) {
+            return false;
+        }
+        cpos = cpos + dir;
+    }
+    return true;
+}
+
+/// Every cell touched by the segment between the centres of `a` and `b`,
+/// both included, in order. Where the segment passes exactly through a grid
+/// corner, the two cells it only touches at that corner are listed too.
+pub fn cells_on_line(a: Pos, b: Pos) -> Vec<Pos> {
+    let mut cells = vec![a];
+    trace_line(a, b, |cpos, dir| {
+        if dir.x != 0 && dir.y != 0 {
+            cells.push(cpos + dir.xonly());
+            cells.push(cpos + dir.yonly());
+        }
+        cells.push(cpos + dir);
+        return true;
+    });
+    return cells;
+}
+
+// 任意两点之间的版本（Amanatides–Woo），visit的含义同上
+pub(crate) fn trace_segment(a: Point, b: Point, mut visit: impl FnMut(Pos, Pos) -> bool) -> bool {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let mut cpos = a.cell();
+    let end = b.cell();
+    let sign = (end - cpos).signxy();
+    let t_delta_x = 1.0 / dx.abs();
+    let t_delta_y = 1.0 / dy.abs();
+    let mut t_max_x = if dx > 0.0 {
+        (cpos.x as f64 + 1.0 - a.x) / dx
+    } else {
+        (a.x - cpos.x as f64) / -dx
+    };
+    let mut t_max_y = if dy > 0.0 {
+        (cpos.y as f64 + 1.0 - a.y) / dy
+    } else {
+        (a.y - cpos.y as f64) / -dy
+    };
+    // 浮点误差可能让最后一步走偏，步数不超过两端格子的曼哈顿距离
+    let diff = end - cpos;
+    let mut remaining = diff.x.abs() + diff.y.abs();
+    while cpos != end && remaining > 0 {
+        let dir = if (t_max_x - t_max_y).abs() < 1e-9 && sign.x != 0 && sign.y != 0 {
+            t_max_x += t_delta_x;
+            t_max_y += t_delta_y;
+            remaining -= 2;
+            sign
+        } else if (t_max_x < t_max_y && sign.x != 0) || sign.y == 0 {
+            t_max_x += t_delta_x;
+            remaining -= 1;
+            sign.xonly()
+        } else {
+            t_max_y += t_delta_y;
+            remaining -= 1;
+            sign.yonly()
+        };
+        if !visit(cpos, dir) {
+            return false;
+        }
+        cpos = cpos + dir;
+    }
+    return cpos == end;
+}
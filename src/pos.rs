@@ -0,0 +1,124 @@
+use std::fmt;
+use std::fmt::Debug;
+use std::ops::{Add, Sub};
+
+/// A cell coordinate. `x` grows to the right and `y` grows downwards;
+/// cell `(x, y)` is stored at `y * width + x`. Coordinates are `i32` on
+/// every target, so native and wasm builds find the same paths.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Pos {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Debug for Pos {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({},{})", self.x, self.y)
+    }
+}
+
+macro_rules! pos {
+    ($x:expr,$y:expr) => {
+        Pos { x: $x, y: $y }
+    };
+}
+
+fn sign_i32(x: i32) -> i32 {
+    return if x > 0 {
+        1
+    } else if x == 0 {
+        0
+    } else {
+        -1
+    };
+}
+
+impl Pos {
+    pub fn new(x: i32, y: i32) -> Self {
+        return pos!(x, y);
+    }
+
+    pub(crate) fn xonly(&self) -> Self {
+        pos!(self.x, 0)
+    }
+    pub(crate) fn yonly(&self) -> Self {
+        pos!(0, self.y)
+    }
+    pub(crate) fn flipxy(&self) -> Self {
+        pos!(self.y, self.x)
+    }
+    pub(crate) fn signxy(&self) -> Self {
+        pos!(sign_i32(self.x), sign_i32(self.y))
+    }
+
+    // 叉积和点积在i64里算，地图边长过了46341时i32会溢出
+    pub(crate) fn cross(&self, other: Self) -> i64 {
+        self.x as i64 * other.y as i64 - self.y as i64 * other.x as i64
+    }
+    pub(crate) fn dot(&self, other: Self) -> i64 {
+        self.x as i64 * other.x as i64 + self.y as i64 * other.y as i64
+    }
+    pub(crate) fn length(&self) -> f64 {
+        (self.dot(*self) as f64).sqrt()
+    }
+    // 定点小数的长度，低fraction位是小数，向下取整；全程整数运算
+    pub(crate) fn fixed_length(&self, fraction: u32) -> i64 {
+        ((self.dot(*self) as u128) << (2 * fraction)).isqrt() as i64
+    }
+}
+
+impl Add for Pos {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        pos!(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Pos {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        pos!(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl From<(i32, i32)> for Pos {
+    fn from(xy: (i32, i32)) -> Self {
+        return pos!(xy.0, xy.1);
+    }
+}
+
+/// A position inside the map, in cell units: cell `(x, y)` covers
+/// `x..x + 1` by `y..y + 1`, so its centre is `(x + 0.5, y + 0.5)`.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    pub fn new(x: f64, y: f64) -> Self {
+        return Self { x, y };
+    }
+
+    /// Centre of a cell.
+    pub fn center(cell: Pos) -> Self {
+        return Self::new(cell.x as f64 + 0.5, cell.y as f64 + 0.5);
+    }
+
+    /// The cell containing this point.
+    pub fn cell(&self) -> Pos {
+        return pos!(self.x.floor() as i32, self.y.floor() as i32);
+    }
+}
+
+impl From<(f64, f64)> for Point {
+    fn from(xy: (f64, f64)) -> Self {
+        return Self::new(xy.0, xy.1);
+    }
+}
+
+impl From<(f32, f32)> for Point {
+    fn from(xy: (f32, f32)) -> Self {
+        return Self::new(xy.0 as f64, xy.1 as f64);
+    }
+}
@@ -0,0 +1,1596 @@
+use std::cmp;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::fmt::Debug;
+
+#[cfg(feature = "debug")]
+use crate::console_log;
+use crate::error::PathError;
+use crate::flow::FlowField;
+use crate::grid::{DiagonalMode, Grid};
+#[cfg(all(feature = "debug", feature = "wasm"))]
+use crate::line::cells_on_line;
+use crate::pos::Pos;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+#[derive(Clone, Copy)]
+pub(crate) struct Pointinfo {
+    pub(crate) position: Pos,
+    pub(crate) distance: i64,
+    pub(crate) dist_gh: i64,
+    pub(crate) tie: TieBreak,
+}
+
+impl PartialEq for Pointinfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Pointinfo {}
+
+impl PartialOrd for Pointinfo {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// BinaryHeap先弹出最大的：估价小的在前，相同时按tie比，最后按位置，行优先、小的在前。
+// 这样弹出的顺序只和openlist里有什么有关，和放进去的先后无关
+impl Ord for Pointinfo {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let by_cost = match self.tie {
+            TieBreak::HigherCost => self.distance.cmp(&other.distance),
+            TieBreak::LowerCost => other.distance.cmp(&self.distance),
+            TieBreak::Position => Ordering::Equal,
+        };
+        let (a, b) = (self.position, other.position);
+        return other
+            .dist_gh
+            .cmp(&self.dist_gh)
+            .then(by_cost)
+            .then((b.y, b.x).cmp(&(a.y, a.x)));
+    }
+}
+
+impl Debug for Pointinfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?}={:?}~{:?}",
+            self.position, self.distance, self.dist_gh
+        )
+    }
+}
+
+// 一格的搜索状态。stamp不是当前编号的格子都当作没碰过，见JpsSearch::reset；
+// target标出find_many的起点，也跟着编号一起失效
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    stamp: u32,
+    from: Pos,
+    distance: i64,
+    closed: bool,
+    target: bool,
+}
+
+impl Node {
+    const UNVISITED: Node = Node {
+        stamp: 0,
+        from: pos!(-1, -1),
+        distance: UNREACHED,
+        closed: false,
+        target: false,
+    };
+}
+
+// 到不了的格子的代价
+pub(crate) const UNREACHED: i64 = i64::MAX;
+
+// Float代价和任意角度搜索用的定点小数的位数，以及这样表示的√2
+pub(crate) const FRACTION: u32 = 24;
+const SQRT_2_FIXED: i64 = 23726566;
+
+pub(crate) const RUSHDIR: [Pos; 4] = [pos!(1, 0), pos!(-1, 0), pos!(0, 1), pos!(0, -1)];
+pub(crate) const DIAGDIR: [Pos; 4] = [pos!(1, 1), pos!(-1, 1), pos!(-1, -1), pos!(1, -1)];
+
+/// Which algorithm [`JpsSearch::find`] runs.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SearchMode {
+    /// Jump point search. Only valid for uniform costs, so weighted grids
+    /// fall back to [`SearchMode::AStar`].
+    #[default]
+    Jps,
+    /// JPS+: jump point search that looks up precomputed jump distances
+    /// instead of scanning the grid. Needs [`Grid::set_jump_table`];
+    /// without the table it is the same as [`SearchMode::Jps`].
+    JpsPlus,
+    /// Plain A* over the 8 neighbours of each cell.
+    AStar,
+    /// Any-angle Theta*: a cell may take its parent's parent directly when
+    /// there is line of sight. The cost is the Euclidean length of the path
+    /// times the straight step cost.
+    ThetaStar,
+    /// Lazy Theta*: like [`SearchMode::ThetaStar`], but line of sight is only
+    /// checked when a cell is expanded. Needs far fewer checks, at the price
+    /// of a slightly longer path now and then.
+    LazyThetaStar,
+}
+
+/// Cost of one straight and one diagonal step, used for the search, its
+/// heuristic and the reported path cost alike.
+///
+/// Costs are added and compared as integers, so native and wasm builds
+/// expand the same cells and return the same paths. [`CostModel::Integer`]
+/// and [`CostModel::FixedOctile`] are exact. The two exceptions are
+/// [`CostModel::Float`] and the any-angle modes ([`SearchMode::ThetaStar`]
+/// and [`SearchMode::LazyThetaStar`]), which need `√2` and Euclidean
+/// lengths: they run in fixed point with 24 fractional bits, so their costs
+/// are rounded down to a multiple of 2<sup>-24</sup>. Only the reported
+/// costs are `f64`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CostModel {
+    /// 2 per straight step and 3 per diagonal step.
+    #[default]
+    Integer,
+    /// Fixed-point octile costs, e.g. `1000` and `1414`. The heuristic and
+    /// the jump point pruning only hold for `0 < straight <= diagonal <
+    /// 2 * straight`; [`JpsSearch::set_cost_model`] rejects anything else.
+    /// At `diagonal == 2 * straight` a diagonal step is no better than the
+    /// two straight steps it replaces, and pruning can miss the shortest path.
+    FixedOctile { straight: u32, diagonal: u32 },
+    /// `1.0` per straight step and `√2` per diagonal step, in fixed point.
+    Float,
+}
+
+// 一次搜索里直走、斜走一步的整数代价，低fraction位是小数
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Units {
+    straight: i64,
+    diagonal: i64,
+    fraction: u32,
+}
+
+impl CostModel {
+    pub fn straight(&self) -> f64 {
+        return match *self {
+            CostModel::Integer => 2.0,
+            CostModel::FixedOctile { straight, .. } => straight as f64,
+            CostModel::Float => 1.0,
+        };
+    }
+
+    pub fn diagonal(&self) -> f64 {
+        return match *self {
+            CostModel::Integer => 3.0,
+            CostModel::FixedOctile { diagonal, .. } => diagonal as f64,
+            CostModel::Float => std::f64::consts::SQRT_2,
+        };
+    }
+
+    // 整数模型直接用步长；Float和任意角度搜索要表示√2和直线距离，改用定点小数
+    fn units(&self, any_angle: bool) -> Units {
+        let (straight, diagonal) = match *self {
+            CostModel::Integer => (2, 3),
+            CostModel::FixedOctile { straight, diagonal } => (straight as i64, diagonal as i64),
+            CostModel::Float => {
+                return Units {
+                    straight: 1 << FRACTION,
+                    diagonal: SQRT_2_FIXED,
+                    fraction: FRACTION,
+                };
+            }
+        };
+        if any_angle {
+            return Units {
+                straight: straight << FRACTION,
+                diagonal: diagonal << FRACTION,
+                fraction: FRACTION,
+            };
+        }
+        return Units {
+            straight,
+            diagonal,
+            fraction: 0,
+        };
+    }
+
+    // 斜走一步不能比直走一步便宜，也不能和直走两步一样贵或更贵，否则跳点剪枝会漏掉最短路
+    fn check(&self) -> Result<(), PathError> {
+        if let CostModel::FixedOctile { straight, diagonal } = *self {
+            if straight == 0 || diagonal < straight || diagonal as u64 >= 2 * straight as u64 {
+                return Err(PathError::BadCostModel);
+            }
+        }
+        return Ok(());
+    }
+}
+
+/// Where a search spread over several calls stands; see [`SearchState`].
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchStatus {
+    /// Out of budget for now; call `step` again to go on.
+    Running,
+    Found,
+    Unreachable,
+}
+
+/// Which of several cells with the same estimated total cost the search
+/// expands first. Whatever the policy, remaining ties go to the cell that
+/// comes first in row-major order, so the same grid and query always give
+/// the same path, however the cells were reached.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TieBreak {
+    /// Prefer the higher cost so far, i.e. the lower heuristic: follows one
+    /// path towards the goal before its equally good alternatives, which
+    /// expands the fewest cells.
+    #[default]
+    HigherCost,
+    /// Prefer the lower cost so far, spreading out evenly from the start.
+    LowerCost,
+    /// Only the position decides.
+    Position,
+}
+
+/// Jump point search over a [`Grid`], or one of the other [`SearchMode`]s.
+/// Weighted grids are always searched with plain A*.
+///
+/// Follows the grid's [`DiagonalMode`]; with [`DiagonalMode::Never`] it runs
+/// the 4-neighbour variant with a Manhattan heuristic and every returned
+/// segment is axis-aligned.
+///
+/// Owns the scratch buffers of the search so that repeated queries do not
+/// reallocate them, and a query only pays for the cells it touches rather
+/// than for the size of the map. One `JpsSearch` can be used with any
+/// number of grids.
+#[derive(Debug, Default)]
+pub struct JpsSearch {
+    mode: SearchMode,
+    costs: CostModel,
+    tie_break: TieBreak,
+    max_expansions: Option<usize>,
+    fallback_to_closest: bool,
+    units: Units,
+    forward: bool,
+    exhaustive: bool,
+    nodes: Vec<Node>,
+    stamp: u32,
+    many: bool,
+    pub(crate) openlist: BinaryHeap<Pointinfo>,
+    expanded: usize,
+    cost: Option<i64>,
+    goal: Option<usize>,
+    best: Option<(i64, i64, Pos)>,
+    partial: bool,
+}
+
+// 直走时这一格是否有强迫邻居
+pub(crate) fn straight_forced(grid: &Grid, pos: Pos, dir: Pos) -> bool {
+    let side = dir.flipxy();
+    return match grid.diagonal() {
+        DiagonalMode::Always | DiagonalMode::IfAtMostOneObstacle => {
+            (!grid.can_walk(pos - side) && grid.can_walk(pos - side + dir))
+                || (!grid.can_walk(pos + side) && grid.can_walk(pos + side + dir))
+        }
+        // 不能切墙角时，刚绕过障碍物的那一格才是跳点
+        DiagonalMode::OnlyWhenNoObstacles | DiagonalMode::Never => {
+            (grid.can_walk(pos - side) && !grid.can_walk(pos - side - dir))
+                || (grid.can_walk(pos + side) && !grid.can_walk(pos + side - dir))
+        }
+    };
+}
+
+// 斜走时这一格是否有强迫邻居，不能切墙角时没有
+pub(crate) fn diag_forced(grid: &Grid, pos: Pos, dir: Pos) -> bool {
+    return match grid.diagonal() {
+        DiagonalMode::Always | DiagonalMode::IfAtMostOneObstacle => {
+            (!grid.can_walk(pos - dir.xonly()) && grid.can_walk(pos - dir.xonly() + dir.yonly()))
+                || (!grid.can_walk(pos - dir.yonly())
+                    && grid.can_walk(pos - dir.yonly() + dir.xonly()))
+        }
+        DiagonalMode::OnlyWhenNoObstacles | DiagonalMode::Never => false,
+    };
+}
+
+impl JpsSearch {
+    pub fn new() -> Self {
+        return Self {
+            mode: SearchMode::Jps,
+            costs: CostModel::Integer,
+            tie_break: TieBreak::HigherCost,
+            max_expansions: None,
+            fallback_to_closest: false,
+            units: Units::default(),
+            forward: false,
+            exhaustive: false,
+            nodes: Vec::new(),
+            stamp: 0,
+            many: false,
+            openlist: BinaryHeap::new(),
+            expanded: 0,
+            cost: None,
+            goal: None,
+            best: None,
+            partial: false,
+        };
+    }
+
+    pub fn mode(&self) -> SearchMode {
+        return self.mode;
+    }
+
+    pub fn set_mode(&mut self, mode: SearchMode) {
+        self.mode = mode;
+    }
+
+    pub fn cost_model(&self) -> CostModel {
+        return self.costs;
+    }
+
+    pub fn set_cost_model(&mut self, costs: CostModel) -> Result<(), PathError> {
+        costs.check()?;
+        self.costs = costs;
+        return Ok(());
+    }
+
+    pub fn tie_break(&self) -> TieBreak {
+        return self.tie_break;
+    }
+
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.tie_break = tie_break;
+    }
+
+    pub fn max_expansions(&self) -> Option<usize> {
+        return self.max_expansions;
+    }
+
+    /// Caps the number of nodes [`JpsSearch::find`] may expand. A search
+    /// that runs out returns the best path so far, flagged by
+    /// [`JpsSearch::is_partial`].
+    pub fn set_max_expansions(&mut self, limit: Option<usize>) {
+        self.max_expansions = limit;
+    }
+
+    pub fn fallback_to_closest(&self) -> bool {
+        return self.fallback_to_closest;
+    }
+
+    /// When the goal cannot be reached, have [`JpsSearch::find`] return a
+    /// path to the reachable cell closest to it instead of an empty path,
+    /// flagged by [`JpsSearch::is_partial`]. Closest means the smallest
+    /// heuristic distance to the goal, then the cheapest path.
+    pub fn set_fallback_to_closest(&mut self, fallback: bool) {
+        self.fallback_to_closest = fallback;
+    }
+
+    // 只复制设置，不动缓冲区
+    fn copy_options(&mut self, other: &JpsSearch) {
+        self.mode = other.mode;
+        self.costs = other.costs;
+        self.tie_break = other.tie_break;
+        self.max_expansions = other.max_expansions;
+        self.fallback_to_closest = other.fallback_to_closest;
+    }
+
+    // 复用上一次搜索分配的空间，不再每次重新分配。
+    // 每格的状态也不清空，换一个编号后旧编号的格子就都当作没碰过
+    pub(crate) fn reset(&mut self, grid: &Grid) {
+        if self.stamp == u32::MAX {
+            self.nodes.iter_mut().for_each(|node| node.stamp = 0);
+            self.stamp = 0;
+        }
+        self.stamp += 1;
+        self.nodes.resize(grid.map.len(), Node::UNVISITED);
+        self.units = self.costs.units(self.any_angle(grid));
+        self.many = false;
+        self.openlist.clear();
+        self.expanded = 0;
+        self.cost = None;
+        self.goal = None;
+        self.forward = false;
+        self.exhaustive = false;
+        self.best = None;
+        self.partial = false;
+    }
+
+    // 任意角度的搜索不受八方向限制，只能用直线距离估计
+    fn any_angle(&self, grid: &Grid) -> bool {
+        return (self.mode == SearchMode::ThetaStar || self.mode == SearchMode::LazyThetaStar)
+            && !grid.is_weighted();
+    }
+
+    fn jump_search(&self, grid: &Grid) -> bool {
+        return (self.mode == SearchMode::Jps || self.mode == SearchMode::JpsPlus)
+            && !grid.is_weighted()
+            && !self.exhaustive;
+    }
+
+    // 一次找多个起点时要停在每个起点上，查表做不到，还是一格一格扫
+    fn use_jump_table(&self, grid: &Grid) -> bool {
+        return self.mode == SearchMode::JpsPlus && grid.has_jump_table() && !self.many;
+    }
+
+    // 直线距离乘直走一步的代价，straight已经带了FRACTION位小数
+    fn segment_cost(&self, a: Pos, b: Pos) -> i64 {
+        let length = (a - b).fixed_length(FRACTION) as i128;
+        let cost = (self.units.straight as i128 * length) >> FRACTION;
+        return cmp::min(cost, UNREACHED as i128) as i64;
+    }
+
+    pub(crate) fn hfunc(&self, grid: &Grid, a: Pos, b: Pos) -> i64 {
+        if self.any_angle(grid) {
+            return self.segment_cost(a, b);
+        }
+        let diff = a - b;
+        let (dx, dy) = (diff.x.abs(), diff.y.abs());
+        let straight = self.units.straight;
+        // 只能横竖走时用曼哈顿距离
+        if grid.diagonal() == DiagonalMode::Never {
+            return straight.saturating_mul(dx as i64 + dy as i64);
+        }
+        let (short, long) = if dx < dy { (dx, dy) } else { (dy, dx) };
+        return (self.units.diagonal.saturating_mul(short as i64))
+            .saturating_add(straight.saturating_mul((long - short) as i64));
+    }
+
+    fn node(&self, index: usize) -> Node {
+        let node = self.nodes[index];
+        return if node.stamp == self.stamp {
+            node
+        } else {
+            Node::UNVISITED
+        };
+    }
+
+    // 要写的格子，这一轮还没碰过的先恢复成初始状态
+    fn node_mut(&mut self, index: usize) -> &mut Node {
+        let stamp = self.stamp;
+        let node = &mut self.nodes[index];
+        if node.stamp != stamp {
+            *node = Node {
+                stamp,
+                ..Node::UNVISITED
+            };
+        }
+        return node;
+    }
+
+    pub(crate) fn frompos_at(&self, index: usize) -> Pos {
+        return self.node(index).from;
+    }
+
+    pub(crate) fn distance_at(&self, index: usize) -> i64 {
+        return self.node(index).distance;
+    }
+
+    fn is_closed(&self, index: usize) -> bool {
+        return self.node(index).closed;
+    }
+
+    fn point_add(&mut self, grid: &Grid, point: Pos, end: Pos, dist: i64, from: Pos) {
+        let hval = self.hfunc(grid, point, end);
+        return self.point_add_hval(grid, point, dist, from, hval);
+    }
+
+    pub(crate) fn point_add_hval(
+        &mut self,
+        grid: &Grid,
+        point: Pos,
+        dist: i64,
+        from: Pos,
+        hval: i64,
+    ) {
+        if grid.can_walk(point) {
+            let index = grid.index(point);
+            let node = self.node_mut(index);
+            if node.distance > dist {
+                node.from = from;
+                node.distance = dist;
+                self.openlist.push(Pointinfo {
+                    position: point,
+                    distance: dist,
+                    dist_gh: dist.saturating_add(hval),
+                    tie: self.tie_break,
+                });
+            }
+        }
+    }
+
+    // 跳跃必须停在要找的格子上；一次找多个起点时它们都算
+    fn is_target(&self, index: usize, pos: Pos, end: Pos) -> bool {
+        return pos == end || (self.many && self.node(index).target);
+    }
+
+    fn rushmove(&mut self, grid: &Grid, from: Pos, dist: i64, dir: Pos, end: Pos) -> bool {
+        if self.use_jump_table(grid) {
+            return self.rushmove_plus(grid, from, dist, dir, end);
+        }
+        return self.rushmove_core(grid, from, dist, dir, end, false);
+    }
+
+    // 从from往dir一直走，撞墙前能不能走到end
+    fn reaches_straight(grid: &Grid, from: Pos, dir: Pos, end: Pos) -> bool {
+        let diff = end - from;
+        let steps = diff.x * dir.x + diff.y * dir.y;
+        if steps <= 0 || diff != pos!(dir.x * steps, dir.y * steps) {
+            return false;
+        }
+        let jump = grid.jump_distance(from, dir);
+        return jump > 0 || -jump >= steps;
+    }
+
+    // 查表版本：跳点、墙和终点中最近的一个
+    fn rushmove_plus(&mut self, grid: &Grid, from: Pos, dist: i64, dir: Pos, end: Pos) -> bool {
+        let jump = grid.jump_distance(from, dir);
+        let reach = jump.abs();
+        let mut stop = if jump > 0 { Some(jump) } else { None };
+        let diff = end - from;
+        let steps = diff.x * dir.x + diff.y * dir.y;
+        if steps > 0 && steps <= reach {
+            let cell = from + pos!(dir.x * steps, dir.y * steps);
+            // 终点就在前面，或者只能横竖走时横着能直达终点
+            let hits = cell == end
+                || (grid.diagonal() == DiagonalMode::Never
+                    && dir.x == 0
+                    && (Self::reaches_straight(grid, cell, pos!(1, 0), end)
+                        || Self::reaches_straight(grid, cell, pos!(-1, 0), end)));
+            if hits && stop.is_none_or(|stop| steps < stop) {
+                stop = Some(steps);
+            }
+        }
+        return match stop {
+            Some(steps) => {
+                let pos = from + pos!(dir.x * steps, dir.y * steps);
+                let dist = dist.saturating_add(self.units.straight.saturating_mul(steps as i64));
+                self.point_add(grid, pos, end, dist, from);
+                true
+            }
+            None => false,
+        };
+    }
+
+    fn diagmove_plus(&mut self, grid: &Grid, from: Pos, dist: i64, dir: Pos, end: Pos) -> bool {
+        let jump = grid.jump_distance(from, dir);
+        let reach = jump.abs();
+        let mut stop = if jump > 0 { Some(jump) } else { None };
+        // 终点在斜线上，或者从斜线上某一格横着、竖着能直达终点
+        let diff = end - from;
+        for steps in [diff.x * dir.x, diff.y * dir.y] {
+            if steps <= 0 || steps > reach || stop.is_some_and(|stop| steps >= stop) {
+                continue;
+            }
+            let cell = from + pos!(dir.x * steps, dir.y * steps);
+            if cell == end
+                || Self::reaches_straight(grid, cell, dir.xonly(), end)
+                || Self::reaches_straight(grid, cell, dir.yonly(), end)
+            {
+                stop = Some(steps);
+            }
+        }
+        return match stop {
+            Some(steps) => {
+                let pos = from + pos!(dir.x * steps, dir.y * steps);
+                let dist = dist.saturating_add(self.units.diagonal.saturating_mul(steps as i64));
+                self.point_add(grid, pos, end, dist, from);
+                true
+            }
+            None => false,
+        };
+    }
+
+    fn rushmove_test(&mut self, grid: &Grid, from: Pos, dist: i64, dir: Pos, end: Pos) -> bool {
+        return self.rushmove_core(grid, from, dist, dir, end, true);
+    }
+
+    fn rushmove_core(
+        &mut self,
+        grid: &Grid,
+        from: Pos,
+        dist: i64,
+        dir: Pos,
+        end: Pos,
+        testing: bool,
+    ) -> bool {
+        if grid.is_bit_packed() && !self.many && grid.diagonal() != DiagonalMode::Never {
+            return self.rushmove_bits(grid, from, dist, dir, end, testing);
+        }
+        let step = self.units.straight;
+        let mut pos = from + dir;
+        let mut dist = dist.saturating_add(step);
+        loop {
+            if !grid.can_walk(pos) {
+                return false;
+            }
+            let index = grid.index(pos);
+            if self.is_target(index, pos, end)
+                || self.distance_at(index) != UNREACHED
+                || straight_forced(grid, pos, dir)
+                || (grid.diagonal() == DiagonalMode::Never
+                    && dir.x == 0
+                    && (self.rushmove_test(grid, pos, dist, pos!(1, 0), end)
+                        || self.rushmove_test(grid, pos, dist, pos!(-1, 0), end)))
+            {
+                if !testing {
+                    self.point_add(grid, pos, end, dist, from);
+                }
+                return true;
+            }
+            pos = pos + dir;
+            dist = dist.saturating_add(step);
+        }
+    }
+
+    // 按位扫的版本，一次看64格；不像一格一格扫那样停在已经到过的格子上
+    fn rushmove_bits(
+        &mut self,
+        grid: &Grid,
+        from: Pos,
+        dist: i64,
+        dir: Pos,
+        end: Pos,
+        testing: bool,
+    ) -> bool {
+        let (mut steps, mut jump) = grid.scan_line(from, dir);
+        let diff = end - from;
+        let to_end = diff.x * dir.x + diff.y * dir.y;
+        // 终点在这条线上、比停下的地方近，就停在终点
+        if to_end > 0 && to_end < steps && diff == pos!(dir.x * to_end, dir.y * to_end) {
+            steps = to_end;
+            jump = true;
+        }
+        if !jump {
+            return false;
+        }
+        if !testing {
+            let pos = from + pos!(dir.x * steps, dir.y * steps);
+            let dist = dist.saturating_add(self.units.straight.saturating_mul(steps as i64));
+            self.point_add(grid, pos, end, dist, from);
+        }
+        return true;
+    }
+
+    fn diagmove(&mut self, grid: &Grid, from: Pos, dist: i64, dir: Pos, end: Pos) -> bool {
+        if self.use_jump_table(grid) {
+            return self.diagmove_plus(grid, from, dist, dir, end);
+        }
+        let step = self.units.diagonal;
+        let mut pos = from + dir;
+        let mut dist = dist.saturating_add(step);
+        loop {
+            if !grid.can_step(pos - dir, dir) {
+                return false;
+            }
+            let index = grid.index(pos);
+            if self.is_target(index, pos, end)
+                || self.distance_at(index) != UNREACHED
+                || diag_forced(grid, pos, dir)
+            {
+                self.point_add(grid, pos, end, dist, from);
+                return true;
+            }
+            let turning = self.rushmove_test(grid, pos, dist, dir.xonly(), end)
+                || self.rushmove_test(grid, pos, dist, dir.yonly(), end);
+            if turning {
+                self.point_add(grid, pos, end, dist, from);
+                return true;
+            }
+            pos = pos + dir;
+            dist = dist.saturating_add(step);
+        }
+    }
+
+    fn jps_expand(&mut self, grid: &Grid, pos: Pos, dist: i64, begin: Pos) {
+        let diagonal = grid.diagonal();
+        let index = grid.index(pos);
+        let dir = (pos - self.frompos_at(index)).signxy();
+        if dir == pos!(0, 0) {
+            for dir in RUSHDIR {
+                self.rushmove(grid, pos, dist, dir, begin);
+            }
+            if diagonal != DiagonalMode::Never {
+                for dir in DIAGDIR {
+                    self.diagmove(grid, pos, dist, dir, begin);
+                }
+            }
+        } else if dir.x == 0 || dir.y == 0 {
+            self.rushmove(grid, pos, dist, dir, begin);
+            match diagonal {
+                DiagonalMode::Always | DiagonalMode::IfAtMostOneObstacle => {
+                    if !grid.can_walk(pos - dir.flipxy()) && grid.can_walk(pos - dir.flipxy() + dir)
+                    {
+                        self.diagmove(grid, pos, dist, dir - dir.flipxy(), begin);
+                    }
+                    if !grid.can_walk(pos + dir.flipxy()) && grid.can_walk(pos + dir.flipxy() + dir)
+                    {
+                        self.diagmove(grid, pos, dist, dir + dir.flipxy(), begin);
+                    }
+                }
+                DiagonalMode::OnlyWhenNoObstacles => {
+                    for side in [dir.flipxy(), pos!(0, 0) - dir.flipxy()] {
+                        if grid.can_walk(pos + side) && !grid.can_walk(pos - dir + side) {
+                            self.rushmove(grid, pos, dist, side, begin);
+                            self.diagmove(grid, pos, dist, dir + side, begin);
+                        }
+                    }
+                }
+                DiagonalMode::Never => {
+                    self.rushmove(grid, pos, dist, dir.flipxy(), begin);
+                    self.rushmove(grid, pos, dist, pos!(0, 0) - dir.flipxy(), begin);
+                }
+            }
+        } else {
+            self.rushmove(grid, pos, dist, dir.xonly(), begin);
+            self.rushmove(grid, pos, dist, dir.yonly(), begin);
+            self.diagmove(grid, pos, dist, dir, begin);
+            if diagonal == DiagonalMode::Always || diagonal == DiagonalMode::IfAtMostOneObstacle {
+                if !grid.can_walk(pos - dir.xonly())
+                    && grid.can_walk(pos - dir.xonly() + dir.yonly())
+                {
+                    self.diagmove(grid, pos, dist, dir.yonly() - dir.xonly(), begin);
+                }
+                if !grid.can_walk(pos - dir.yonly())
+                    && grid.can_walk(pos - dir.yonly() + dir.xonly())
+                {
+                    self.diagmove(grid, pos, dist, dir.xonly() - dir.yonly(), begin);
+                }
+            }
+        }
+    }
+
+    fn astar_expand(&mut self, grid: &Grid, pos: Pos, dist: i64, begin: Pos) {
+        // 反向搜索，从next走到pos时进入的是pos这一格；正向搜索时进入的是next
+        let cost = grid.cost(pos) as i64;
+        for dir in RUSHDIR.iter().chain(DIAGDIR.iter()) {
+            let next = pos + *dir;
+            if !grid.can_step(pos, *dir) {
+                continue;
+            }
+            let cost = if self.forward {
+                grid.cost(next) as i64
+            } else {
+                cost
+            };
+            let step = if dir.x != 0 && dir.y != 0 {
+                self.units.diagonal
+            } else {
+                self.units.straight
+            };
+            let dist = dist.saturating_add(step.saturating_mul(cost));
+            self.point_add(grid, next, begin, dist, pos);
+        }
+    }
+
+    fn theta_expand(&mut self, grid: &Grid, pos: Pos, dist: i64, begin: Pos) {
+        let parent = self.frompos_at(grid.index(pos));
+        let lazy = self.mode == SearchMode::LazyThetaStar;
+        for dir in RUSHDIR.iter().chain(DIAGDIR.iter()) {
+            let next = pos + *dir;
+            if !grid.can_step(pos, *dir) || self.is_closed(grid.index(next)) {
+                continue;
+            }
+            // 能直接看到父节点就跳过当前格，Lazy版本留到展开时再检查
+            if lazy || grid.line_of_sight(parent, next) {
+                let dist = self
+                    .distance_at(grid.index(parent))
+                    .saturating_add(self.segment_cost(parent, next));
+                self.point_add(grid, next, begin, dist, parent);
+            } else {
+                let dist = dist.saturating_add(self.segment_cost(pos, next));
+                self.point_add(grid, next, begin, dist, pos);
+            }
+        }
+    }
+
+    // Lazy Theta*：父节点其实看不到这一格时，改从已关闭的邻居中最近的一个过来
+    fn lazy_set_parent(&mut self, grid: &Grid, pos: Pos) {
+        let index = grid.index(pos);
+        if grid.line_of_sight(self.frompos_at(index), pos) {
+            return;
+        }
+        let mut best = UNREACHED;
+        for dir in RUSHDIR.iter().chain(DIAGDIR.iter()) {
+            let prev = pos + *dir;
+            if grid.can_step(pos, *dir) && self.is_closed(grid.index(prev)) {
+                let dist = self
+                    .distance_at(grid.index(prev))
+                    .saturating_add(self.segment_cost(prev, pos));
+                if dist < best {
+                    best = dist;
+                    self.node_mut(index).from = prev;
+                }
+            }
+        }
+        self.node_mut(index).distance = best;
+    }
+
+    fn expand(&mut self, grid: &Grid, pos: Pos, begin: Pos) {
+        self.expanded += 1;
+        let dist = self.distance_at(grid.index(pos));
+        // JPS和任意角度搜索只在所有格子代价相同时成立，带权地图一律用普通A*
+        if self.jump_search(grid) {
+            self.jps_expand(grid, pos, dist, begin);
+        } else if self.any_angle(grid) {
+            self.theta_expand(grid, pos, dist, begin);
+        } else {
+            self.astar_expand(grid, pos, dist, begin);
+        }
+    }
+
+    // 从已经放进openlist的起点（反向搜索时就是终点们）一直搜到begin，
+    // 展开的节点数到limit时暂停
+    fn run(&mut self, grid: &Grid, begin: Pos, limit: usize) -> SearchStatus {
+        let any_angle = self.any_angle(grid);
+
+        while self.expanded < limit {
+            let pinfo = match self.openlist.pop() {
+                Some(pinfo) => pinfo,
+                None => return SearchStatus::Unreachable,
+            };
+            #[cfg(feature = "debug")]
+            console_log(format!("{:?} <- {:?} ", pinfo, self.openlist).as_str());
+
+            let pos = pinfo.position;
+            let dist = pinfo.distance;
+
+            let index = grid.index(pos);
+            if dist == self.distance_at(index) && !self.is_closed(index) {
+                self.node_mut(index).closed = true;
+                if any_angle && self.mode == SearchMode::LazyThetaStar {
+                    self.lazy_set_parent(grid, pos);
+                }
+                if pos == begin {
+                    return SearchStatus::Found;
+                }
+                // 记下离目标最近的格子，搜不完时走到这里
+                if self.forward {
+                    let best = (self.hfunc(grid, pos, begin), self.distance_at(index), pos);
+                    if self.best.is_none_or(|(h, g, _)| (best.0, best.1) < (h, g)) {
+                        self.best = Some(best);
+                    }
+                }
+                self.expand(grid, pos, begin);
+            }
+
+            #[cfg(feature = "debug")]
+            console_log(self.debug(grid).as_str());
+        }
+        if self.openlist.is_empty() {
+            return SearchStatus::Unreachable;
+        }
+        return SearchStatus::Running;
+    }
+
+    // JPS只关闭跳点，确定到不了终点后用A*把整个连通区域再走一遍，
+    // 才能找到真正最近的格子。返回false表示已经不用重搜
+    fn restart_exhaustive(&mut self, grid: &Grid, begin: Pos, end: Pos) -> bool {
+        if !self.jump_search(grid) {
+            return false;
+        }
+        let expanded = self.expanded;
+        self.reset(grid);
+        self.expanded = expanded;
+        self.forward = true;
+        self.exhaustive = true;
+        self.point_add(grid, begin, end, 0, begin);
+        return true;
+    }
+
+    // 正向搜索时，从搜索起点走到to的路径
+    fn trace_forward(&mut self, grid: &Grid, to: Pos) -> Vec<Pos> {
+        let mut path = self.trace(grid, to);
+        path.reverse();
+        return path;
+    }
+
+    // 没搜到时退而求其次，走到离目标最近的已展开格子
+    fn trace_best(&mut self, grid: &Grid) -> Vec<Pos> {
+        return match self.best {
+            Some((_, _, pos)) => {
+                self.partial = true;
+                self.trace_forward(grid, pos)
+            }
+            None => Vec::new(),
+        };
+    }
+
+    // 从begin沿frompos走回搜索的起点，没搜到时为空
+    fn trace(&mut self, grid: &Grid, begin: Pos) -> Vec<Pos> {
+        let mut path = Vec::new();
+
+        if self.distance_at(grid.index(begin)) != UNREACHED {
+            let mut find = begin;
+            let mut cdir = pos!(0, 0);
+            loop {
+                let next = self.frompos_at(grid.index(find));
+                if next == find {
+                    break;
+                }
+                let dir = find - next;
+
+                // 如果连续三个点在同一条直线上，则不输出第二个点
+                if cdir == pos!(0, 0) || dir.cross(cdir) != 0 {
+                    path.push(find);
+                }
+
+                find = next;
+                cdir = dir;
+            }
+            path.push(find);
+        }
+
+        if !path.is_empty() {
+            self.cost = Some(self.distance_at(grid.index(begin)));
+        }
+
+        return path;
+    }
+
+    /// Searches for a path from `begin` to `end`.
+    ///
+    /// Returns the turning points from `begin` to `end`, both included, with
+    /// collinear points removed. The list is empty when `end` cannot be reached;
+    /// that is known without searching when the two are in different regions
+    /// of the grid, see [`Grid::is_reachable`].
+    pub fn find(&mut self, grid: &Grid, begin: Pos, end: Pos) -> Result<Vec<Pos>, PathError> {
+        grid.check_endpoints(begin, end)?;
+
+        self.reset(grid);
+        let reachable = grid.is_reachable(begin, end);
+        if !reachable && !self.fallback_to_closest {
+            return Ok(Vec::new());
+        }
+        let limit = match self.max_expansions {
+            Some(limit) => limit,
+            None if self.fallback_to_closest => usize::MAX,
+            None => {
+                self.point_add(grid, end, begin, 0, end);
+                self.run(grid, begin, usize::MAX);
+                let path = self.trace(grid, begin);
+                if !path.is_empty() {
+                    self.goal = Some(0);
+                }
+                return Ok(path);
+            }
+        };
+
+        // 有预算时正向搜索，搜不完也能给出从起点出发的一段路
+        self.forward = true;
+        self.exhaustive = !reachable;
+        self.point_add(grid, begin, end, 0, begin);
+        let mut status = self.run(grid, end, limit);
+        if status == SearchStatus::Unreachable
+            && self.fallback_to_closest
+            && self.restart_exhaustive(grid, begin, end)
+        {
+            status = self.run(grid, end, limit);
+        }
+        let path = match status {
+            SearchStatus::Found => self.trace_forward(grid, end),
+            SearchStatus::Running => self.trace_best(grid),
+            SearchStatus::Unreachable if self.fallback_to_closest => self.trace_best(grid),
+            SearchStatus::Unreachable => Vec::new(),
+        };
+        if !path.is_empty() && !self.partial {
+            self.goal = Some(0);
+        }
+
+        return Ok(path);
+    }
+
+    /// Starts a search from `begin` to `end` that is carried out a few nodes
+    /// at a time with [`SearchState::step`], e.g. one slice per frame. The
+    /// state takes a copy of this search's options and has its own buffers,
+    /// allocated here with one entry per cell of the map; use
+    /// [`JpsSearch::restart`] to run the next stepped search in them.
+    pub fn start(&self, grid: &Grid, begin: Pos, end: Pos) -> Result<SearchState, PathError> {
+        grid.check_endpoints(begin, end)?;
+
+        let mut state = SearchState {
+            search: Self::new(),
+            begin,
+            end,
+            status: SearchStatus::Unreachable,
+        };
+        self.restart(&mut state, grid, begin, end)?;
+        return Ok(state);
+    }
+
+    /// Like [`JpsSearch::start`], but reuses the buffers of `state`, which is
+    /// replaced by a search from `begin` to `end` with this search's current
+    /// options. Only the cells the new search touches are paid for.
+    pub fn restart(
+        &self,
+        state: &mut SearchState,
+        grid: &Grid,
+        begin: Pos,
+        end: Pos,
+    ) -> Result<(), PathError> {
+        grid.check_endpoints(begin, end)?;
+
+        let search = &mut state.search;
+        search.copy_options(self);
+        search.reset(grid);
+        let reachable = grid.is_reachable(begin, end);
+        state.status = if reachable || search.fallback_to_closest {
+            search.forward = true;
+            search.exhaustive = !reachable;
+            search.point_add(grid, begin, end, 0, begin);
+            SearchStatus::Running
+        } else {
+            SearchStatus::Unreachable
+        };
+        state.begin = begin;
+        state.end = end;
+        return Ok(());
+    }
+
+    /// Searches for a path from `begin` to whichever of `goals` is cheapest
+    /// to reach, in a single search. The path is returned as by
+    /// [`JpsSearch::find`] and ends at that goal; see [`JpsSearch::reached_goal`]
+    /// for its index.
+    pub fn find_nearest(
+        &mut self,
+        grid: &Grid,
+        begin: Pos,
+        goals: &[Pos],
+    ) -> Result<Vec<Pos>, PathError> {
+        for &goal in goals {
+            grid.check_endpoints(begin, goal)?;
+        }
+
+        // 本来就是从终点往回搜，所有终点一起放进openlist即可；
+        // 不连通的终点放进去只会白白搜完它所在的区域
+        self.reset(grid);
+        for &goal in goals {
+            if grid.is_reachable(begin, goal) {
+                self.point_add(grid, goal, begin, 0, goal);
+            }
+        }
+        self.run(grid, begin, usize::MAX);
+        let path = self.trace(grid, begin);
+        if let Some(&goal) = path.last() {
+            self.goal = goals.iter().position(|&pos| pos == goal);
+        }
+
+        return Ok(path);
+    }
+
+    /// Searches for a path from each of `starts` to `goal`, growing one
+    /// search tree from `goal` until every start is reached or known to be
+    /// unreachable. Returns one path per start, as [`JpsSearch::find`] would;
+    /// see [`JpsSearch::cost_to`] for their costs.
+    pub fn find_many(
+        &mut self,
+        grid: &Grid,
+        starts: &[Pos],
+        goal: Pos,
+    ) -> Result<Vec<Vec<Pos>>, PathError> {
+        for &begin in starts {
+            grid.check_endpoints(begin, goal)?;
+        }
+
+        self.reset(grid);
+        self.many = true;
+        for &begin in starts {
+            let index = grid.index(begin);
+            self.node_mut(index).target = true;
+        }
+
+        let mut paths = Vec::with_capacity(starts.len());
+        let mut seeded = false;
+        for &begin in starts {
+            if !grid.is_reachable(begin, goal) {
+                paths.push(Vec::new());
+                continue;
+            }
+            if !seeded {
+                self.point_add(grid, goal, begin, 0, goal);
+                seeded = true;
+            } else if !self.is_closed(grid.index(begin)) {
+                self.retarget(grid, begin);
+            }
+            if !self.is_closed(grid.index(begin)) {
+                self.run(grid, begin, usize::MAX);
+                // run找到begin就停了，后面的起点可能要经过这里
+                if self.is_closed(grid.index(begin)) {
+                    self.expand(grid, begin, begin);
+                }
+            }
+            paths.push(self.trace(grid, begin));
+        }
+        self.cost = None;
+
+        return Ok(paths);
+    }
+
+    // 换一个目标继续搜：按新的估价重排openlist，已关闭的格子距离都是准的
+    fn retarget(&mut self, grid: &Grid, begin: Pos) {
+        let mut openlist = std::mem::take(&mut self.openlist).into_vec();
+        openlist.retain(|pinfo| {
+            let index = grid.index(pinfo.position);
+            return pinfo.distance == self.distance_at(index) && !self.is_closed(index);
+        });
+        for pinfo in openlist.iter_mut() {
+            pinfo.dist_gh = pinfo
+                .distance
+                .saturating_add(self.hfunc(grid, pinfo.position, begin));
+        }
+        self.openlist = BinaryHeap::from(openlist);
+    }
+
+    /// Runs Dijkstra from `goal` over every cell it can reach and returns the
+    /// cost and first step of each cell. Follows the [`DiagonalMode`], the
+    /// [`CostModel`] and weighted terrain like [`SearchMode::AStar`].
+    pub fn flow_field(&mut self, grid: &Grid, goal: Pos) -> Result<FlowField, PathError> {
+        if !grid.contains(goal) {
+            return Err(PathError::OutOfBounds);
+        }
+        if !grid.can_walk(goal) {
+            return Err(PathError::GoalBlocked);
+        }
+
+        self.reset(grid);
+        self.point_add_hval(grid, goal, 0, goal, 0);
+        while let Some(pinfo) = self.openlist.pop() {
+            let pos = pinfo.position;
+            let index = grid.index(pos);
+            if pinfo.distance != self.distance_at(index) || self.is_closed(index) {
+                continue;
+            }
+            self.node_mut(index).closed = true;
+            self.expanded += 1;
+
+            // 和astar_expand一样，只是没有目标，估价为0
+            let cost = grid.cost(pos) as i64;
+            for dir in RUSHDIR.iter().chain(DIAGDIR.iter()) {
+                if grid.can_step(pos, *dir) {
+                    let step = if dir.x != 0 && dir.y != 0 {
+                        self.units.diagonal
+                    } else {
+                        self.units.straight
+                    };
+                    let dist = pinfo.distance.saturating_add(step.saturating_mul(cost));
+                    self.point_add_hval(grid, pos + *dir, dist, pos, 0);
+                }
+            }
+        }
+
+        let len = grid.map.len();
+        let distance = (0..len)
+            .map(|i| self.to_cost(self.distance_at(i)))
+            .collect::<Vec<_>>();
+        let frompos = (0..len).map(|i| self.frompos_at(i)).collect::<Vec<_>>();
+        return Ok(FlowField::new(grid.size, goal, &distance, &frompos));
+    }
+
+    /// Cost from `pos` to the goal in the tree built by the last search, if
+    /// the search got that far.
+    pub fn cost_to(&self, grid: &Grid, pos: Pos) -> Option<f64> {
+        return self.cost_units_to(grid, pos).map(|cost| self.to_cost(cost));
+    }
+
+    // cost_to的整数版本，还没换算成f64
+    pub(crate) fn cost_units_to(&self, grid: &Grid, pos: Pos) -> Option<i64> {
+        if !grid.contains(pos)
+            || grid.index(pos) >= self.nodes.len()
+            || !self.is_closed(grid.index(pos))
+        {
+            return None;
+        }
+        return Some(self.distance_at(grid.index(pos)));
+    }
+
+    /// Index in `goals` of the goal reached by the last
+    /// [`JpsSearch::find_nearest`] (always `0` after [`JpsSearch::find`]).
+    /// `None` if no path was found.
+    pub fn reached_goal(&self) -> Option<usize> {
+        return self.goal;
+    }
+
+    /// Whether the last path returned stops short of the goal: the search ran
+    /// out of budget, or the goal is unreachable and
+    /// [`JpsSearch::set_fallback_to_closest`] is on. The path leads to the
+    /// expanded cell closest to the goal.
+    pub fn is_partial(&self) -> bool {
+        return self.partial;
+    }
+
+    /// Cost of the path found by the last [`JpsSearch::find`], using the
+    /// step costs of the [`CostModel`] times the cost of the entered cell on
+    /// weighted grids. `None` if no path was found.
+    pub fn cost(&self) -> Option<f64> {
+        return self.cost.map(|cost| self.to_cost(cost));
+    }
+
+    pub(crate) fn cost_units(&self) -> Option<i64> {
+        return self.cost;
+    }
+
+    // 上一次搜索里直走一步的整数代价
+    pub(crate) fn straight_units(&self) -> i64 {
+        return self.units.straight;
+    }
+
+    // 报给外面的代价去掉定点小数；到不了的是无穷大
+    pub(crate) fn to_cost(&self, units: i64) -> f64 {
+        if units == UNREACHED {
+            return f64::INFINITY;
+        }
+        return units as f64 / (1u64 << self.units.fraction) as f64;
+    }
+
+    /// Number of nodes taken off the open list by the last search.
+    pub fn nodes_expanded(&self) -> usize {
+        return self.expanded;
+    }
+
+    #[cfg(feature = "debug")]
+    pub(crate) fn debug(&self, grid: &Grid) -> String {
+        let mut dir = String::new();
+        let mut dis = String::new();
+
+        const DIRSYN: [&str; 10] = ["::", "JJ", "vv", "LL", ">>", "88", "<<", "77", "^^", "rr"];
+
+        for y in 0..grid.size.y {
+            for x in 0..grid.size.x {
+                let here = pos!(x, y);
+                let i = grid.index(here);
+                dir += if grid.map[i] == 0 {
+                    if self.frompos_at(i) == pos!(-1, -1) {
+                        "::"
+                    } else {
+                        let cdir = (here - self.frompos_at(i)).signxy();
+                        DIRSYN[((cdir.y + 1) * 3 + (cdir.x + 2)) as usize]
+                    }
+                } else {
+                    "  "
+                };
+                if self.distance_at(i) == UNREACHED {
+                    dis += "  ";
+                } else {
+                    let dstr = (100 + (self.distance_at(i) >> self.units.fraction)).to_string();
+                    dis += &dstr[(dstr.len() - 2)..dstr.len()];
+                }
+            }
+            dir += "\n";
+            dis += "\n";
+        }
+
+        return format!("{}\n{}\n", dir, dis);
+    }
+
+    #[cfg(all(feature = "debug", feature = "wasm"))]
+    pub(crate) fn debug_path(&self, grid: &Grid, path: &[Pos]) -> String {
+        let mut dir = String::new();
+        let mut map = grid.map.iter().map(|x| (x * 10) as i8).collect::<Vec<_>>();
+
+        if !path.is_empty() {
+            // 这里要先判path是否为空，
+            // 否则path.len() - 1作为无符号整数usize会向下溢出
+            for i in 0..(path.len() - 1) {
+                let lpath = cells_on_line(path[i], path[i + 1]);
+                for i in 0..(lpath.len() - 1) {
+                    let index = grid.index(lpath[i]);
+                    let dir = lpath[i] - lpath[i + 1];
+                    let dir = ((dir.y + 1) * 3 + (dir.x + 2)) as i8;
+                    map[index] = dir;
+                }
+            }
+            let index = grid.index(path[path.len() - 1]);
+            map[index] = 5;
+        }
+
+        const DIRSYN: [&str; 11] = [
+            "::", "JJ", "vv", "LL", ">>", "88", "<<", "77", "^^", "rr", "##",
+        ];
+
+        for y in 0..grid.size.y {
+            for x in 0..grid.size.x {
+                let i = grid.index(pos!(x, y));
+                dir += DIRSYN[map[i] as usize];
+            }
+            dir += "\n";
+        }
+
+        return dir;
+    }
+}
+
+/// A search in progress, started by [`JpsSearch::start`].
+///
+/// The grid passed to [`SearchState::step`] must be the one the search was
+/// started on, unchanged.
+#[derive(Debug)]
+pub struct SearchState {
+    search: JpsSearch,
+    begin: Pos,
+    end: Pos,
+    status: SearchStatus,
+}
+
+impl SearchState {
+    /// Expands at most `n` more nodes.
+    pub fn step(&mut self, grid: &Grid, n: usize) -> SearchStatus {
+        if self.status == SearchStatus::Running {
+            let limit = self.search.expanded.saturating_add(n);
+            self.status = self.search.run(grid, self.end, limit);
+            if self.status == SearchStatus::Unreachable
+                && self.search.fallback_to_closest
+                && self.search.restart_exhaustive(grid, self.begin, self.end)
+            {
+                self.status = SearchStatus::Running;
+            }
+        }
+        return self.status;
+    }
+
+    pub fn status(&self) -> SearchStatus {
+        return self.status;
+    }
+
+    /// The path once found; while still running, the best path so far
+    /// (see [`JpsSearch::is_partial`]). Empty if the goal is unreachable,
+    /// unless [`JpsSearch::set_fallback_to_closest`] is on.
+    pub fn path(&mut self, grid: &Grid) -> Vec<Pos> {
+        self.search.partial = false;
+        self.search.cost = None;
+        self.search.goal = None;
+        return match self.status {
+            SearchStatus::Found => {
+                self.search.goal = Some(0);
+                self.search.trace_forward(grid, self.end)
+            }
+            SearchStatus::Running => self.search.trace_best(grid),
+            SearchStatus::Unreachable if self.search.fallback_to_closest => {
+                self.search.trace_best(grid)
+            }
+            SearchStatus::Unreachable => Vec::new(),
+        };
+    }
+
+    /// The underlying search, for [`JpsSearch::cost`],
+    /// [`JpsSearch::is_partial`] and [`JpsSearch::nodes_expanded`] after
+    /// [`SearchState::path`].
+    pub fn search(&self) -> &JpsSearch {
+        return &self.search;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{random_grid, Random, DIAGONAL_MODES};
+
+    // 按路径上每一段的直走、斜走步数算代价
+    fn octile_cost(path: &[Pos], straight: f64, diagonal: f64) -> f64 {
+        let mut cost = 0.0;
+        for pair in path.windows(2) {
+            let diff = pair[1] - pair[0];
+            let (dx, dy) = (diff.x.abs(), diff.y.abs());
+            let short = cmp::min(dx, dy) as f64;
+            cost += diagonal * short + straight * (cmp::max(dx, dy) as f64 - short);
+        }
+        return cost;
+    }
+
+    #[test]
+    fn integer_costs_are_exact() {
+        let mut random = Random::new(23);
+        let models = [
+            CostModel::Integer,
+            CostModel::FixedOctile {
+                straight: 1000,
+                diagonal: 1414,
+            },
+        ];
+        for costs in models {
+            for mode in [SearchMode::Jps, SearchMode::AStar] {
+                let mut search = JpsSearch::new();
+                search.set_mode(mode);
+                search.set_cost_model(costs).unwrap();
+                for diagonal in DIAGONAL_MODES {
+                    let grid = random_grid(&mut random, pos!(40, 30), 25, diagonal);
+                    for _ in 0..50 {
+                        let (begin, end) = (random.pos(&grid), random.pos(&grid));
+                        if !grid.can_walk(begin) || !grid.can_walk(end) {
+                            continue;
+                        }
+                        let path = search.find(&grid, begin, end).unwrap();
+                        if path.is_empty() {
+                            continue;
+                        }
+                        let expected = octile_cost(&path, costs.straight(), costs.diagonal());
+                        assert_eq!(search.cost(), Some(expected), "{:?} {:?}", begin, end);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn jps_costs_match_astar_near_the_diagonal_bound() {
+        let rejected = CostModel::FixedOctile {
+            straight: 5,
+            diagonal: 10,
+        };
+        assert_eq!(
+            JpsSearch::new().set_cost_model(rejected),
+            Err(PathError::BadCostModel)
+        );
+
+        // 紧挨着上下两个边界的几组
+        let models = [(5, 9), (1000, 1999), (5, 5)];
+        for (straight, diagonal) in models {
+            let mut random = Random::new(2);
+            let costs = CostModel::FixedOctile { straight, diagonal };
+            for tie_break in [
+                TieBreak::HigherCost,
+                TieBreak::LowerCost,
+                TieBreak::Position,
+            ] {
+                let mut jps = JpsSearch::new();
+                jps.set_cost_model(costs).unwrap();
+                jps.set_tie_break(tie_break);
+                let mut astar = JpsSearch::new();
+                astar.set_mode(SearchMode::AStar);
+                astar.set_cost_model(costs).unwrap();
+                for diagonal in DIAGONAL_MODES {
+                    let grid = random_grid(&mut random, pos!(70, 40), 28, diagonal);
+                    for _ in 0..400 {
+                        let (begin, end) = (random.pos(&grid), random.pos(&grid));
+                        if !grid.can_walk(begin) || !grid.can_walk(end) {
+                            continue;
+                        }
+                        astar.find(&grid, begin, end).unwrap();
+                        jps.find(&grid, begin, end).unwrap();
+                        assert_eq!(jps.cost(), astar.cost(), "{:?} {:?}", begin, end);
+                        // 分步搜索是正向的，剪枝的方向和find相反
+                        let mut state = jps.start(&grid, begin, end).unwrap();
+                        while state.step(&grid, 64) == SearchStatus::Running {}
+                        state.path(&grid);
+                        assert_eq!(state.search().cost(), astar.cost(), "{:?} {:?}", begin, end);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn any_angle_cost_is_the_path_length() {
+        let mut random = Random::new(123);
+        for mode in [SearchMode::ThetaStar, SearchMode::LazyThetaStar] {
+            for costs in [CostModel::Integer, CostModel::Float] {
+                let mut search = JpsSearch::new();
+                search.set_mode(mode);
+                search.set_cost_model(costs).unwrap();
+                let grid = random_grid(&mut random, pos!(40, 30), 25, DiagonalMode::Always);
+                for _ in 0..50 {
+                    let (begin, end) = (random.pos(&grid), random.pos(&grid));
+                    if !grid.can_walk(begin) || !grid.can_walk(end) {
+                        continue;
+                    }
+                    let path = search.find(&grid, begin, end).unwrap();
+                    if path.is_empty() {
+                        continue;
+                    }
+                    let length = path.windows(2).map(|pair| (pair[1] - pair[0]).length());
+                    let expected = costs.straight() * length.sum::<f64>();
+                    let cost = search.cost().unwrap();
+                    assert!((cost - expected).abs() < 1e-5, "{} {}", cost, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn find_many_matches_find() {
+        let mut random = Random::new(25);
+        let mut many = JpsSearch::new();
+        let mut single = JpsSearch::new();
+        for diagonal in DIAGONAL_MODES {
+            let grid = random_grid(&mut random, pos!(40, 30), 25, diagonal);
+            for _ in 0..20 {
+                let goal = random.pos(&grid);
+                let starts = (0..5)
+                    .map(|_| random.pos(&grid))
+                    .filter(|&pos| grid.can_walk(pos))
+                    .collect::<Vec<_>>();
+                if !grid.can_walk(goal) {
+                    continue;
+                }
+                let paths = many.find_many(&grid, &starts, goal).unwrap();
+                for (&begin, path) in starts.iter().zip(paths.iter()) {
+                    single.find(&grid, begin, goal).unwrap();
+                    assert_eq!(path.is_empty(), single.cost().is_none());
+                    if !path.is_empty() {
+                        assert_eq!(many.cost_to(&grid, begin), single.cost(), "{:?}", begin);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn restarted_state_matches_a_fresh_one() {
+        let mut random = Random::new(125);
+        let mut search = JpsSearch::new();
+        search.set_max_expansions(Some(1000));
+        let grid = random_grid(&mut random, pos!(40, 30), 25, DiagonalMode::Always);
+        let mut reused: Option<SearchState> = None;
+        for i in 0..50 {
+            let (begin, end) = (random.pos(&grid), random.pos(&grid));
+            if !grid.can_walk(begin) || !grid.can_walk(end) {
+                continue;
+            }
+            search.set_fallback_to_closest(i % 2 == 0);
+            let mut fresh = search.start(&grid, begin, end).unwrap();
+            let state = match &mut reused {
+                Some(state) => {
+                    search.restart(state, &grid, begin, end).unwrap();
+                    state
+                }
+                None => reused.insert(search.start(&grid, begin, end).unwrap()),
+            };
+            while fresh.step(&grid, 7) == SearchStatus::Running {}
+            while state.step(&grid, 7) == SearchStatus::Running {}
+            assert_eq!(state.path(&grid), fresh.path(&grid));
+            assert_eq!(state.search().cost(), fresh.search().cost());
+            assert_eq!(state.search().is_partial(), fresh.search().is_partial());
+        }
+    }
+
+    #[test]
+    fn same_query_gives_the_same_path() {
+        let mut random = Random::new(24);
+        let other = random_grid(&mut random, pos!(23, 17), 20, DiagonalMode::Always);
+        for tie_break in [
+            TieBreak::HigherCost,
+            TieBreak::LowerCost,
+            TieBreak::Position,
+        ] {
+            for diagonal in DIAGONAL_MODES {
+                // 左边墙少，等价的路线多；右边用一列墙隔开，在那边随便改。
+                // 连着的地方改了墙，跳点会变，换成另一条一样长的路也是对的
+                let mut grid = random_grid(&mut random, pos!(64, 32), 5, diagonal);
+                grid.set_rect(pos!(32, 0), pos!(32, 31), true);
+                let mut reused = JpsSearch::new();
+                reused.set_tie_break(tie_break);
+                for _ in 0..50 {
+                    let begin = pos!(random.below(32), random.below(32));
+                    let end = pos!(random.below(32), random.below(32));
+                    if !grid.can_walk(begin) || !grid.can_walk(end) {
+                        continue;
+                    }
+                    let mut fresh = JpsSearch::new();
+                    fresh.set_tie_break(tie_break);
+                    let expected = fresh.find(&grid, begin, end).unwrap();
+
+                    // 中间拿去搜别的地图、别的起终点
+                    let (a, b) = (random.pos(&other), random.pos(&other));
+                    if other.can_walk(a) && other.can_walk(b) {
+                        reused.find(&other, a, b).unwrap();
+                    }
+                    let (a, b) = (random.pos(&grid), random.pos(&grid));
+                    if grid.can_walk(a) && grid.can_walk(b) {
+                        reused.find(&grid, a, b).unwrap();
+                    }
+                    assert_eq!(reused.find(&grid, begin, end).unwrap(), expected);
+
+                    for _ in 0..10 {
+                        let far = pos!(33 + random.below(31), random.below(32));
+                        grid.set_cell(far, random.below(3) == 0).unwrap();
+                    }
+                    assert_eq!(reused.find(&grid, begin, end).unwrap(), expected);
+                    assert_eq!(fresh.find(&grid, begin, end).unwrap(), expected);
+                }
+            }
+        }
+    }
+}
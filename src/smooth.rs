@@ -0,0 +1,581 @@
+use std::cmp;
+
+use crate::error::PathError;
+use crate::grid::{DiagonalMode, Grid};
+use crate::pos::{Point, Pos};
+use crate::search::{JpsSearch, FRACTION, UNREACHED};
+
+#[cfg(feature = "debug")]
+use crate::console_log;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// How [`SmoothPath::simplify`] straightens a path.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SmoothMode {
+    /// Shortest chain of corner points inside each stretch where neither x
+    /// nor y changes direction.
+    #[default]
+    Segments,
+    /// String pulling over the whole path: from each kept point, skip to the
+    /// farthest cell along the path that is still in line of sight, across
+    /// any number of turns.
+    StringPull,
+}
+
+/// Where [`SmoothPath::simplify_points`] puts the turning points.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Waypoints {
+    /// At the centre of the turning cell.
+    #[default]
+    Center,
+    /// At the corner of the turning cell on the inside of the turn, i.e. on
+    /// the wall corner being walked around. A turn stays at the cell centre
+    /// when the corner would leave a neighbouring segment without line of
+    /// sight, or, under [`DiagonalMode::OnlyWhenNoObstacles`], when the
+    /// corner touches a wall.
+    Corner,
+}
+
+/// Smoother for the jump point paths found by [`JpsSearch`].
+///
+/// By default each monotone stretch of the path is replaced by the shortest
+/// chain of corner points that still has line of sight; see [`SmoothMode`]
+/// for the alternative. Keeps its own scratch buffers between calls.
+#[derive(Debug, Default)]
+pub struct SmoothPath {
+    mode: SmoothMode,
+    search: JpsSearch,
+}
+
+// 直线距离，定点小数，和任意角度搜索一样按整数加减比较
+fn simphfunc(a: Pos, b: Pos) -> i64 {
+    return (a - b).fixed_length(FRACTION);
+}
+
+// 把横竖和45度斜线的线段展开成一格一格的点，其它线段只保留端点
+fn path_cells(path: &[Pos]) -> Vec<Pos> {
+    let mut cells = Vec::new();
+    for (i, &pos) in path.iter().enumerate() {
+        if i > 0 {
+            let prev = path[i - 1];
+            let diff = pos - prev;
+            if diff.x == 0 || diff.y == 0 || diff.x.abs() == diff.y.abs() {
+                let dir = diff.signxy();
+                let mut cpos = prev + dir;
+                while cpos != pos {
+                    cells.push(cpos);
+                    cpos = cpos + dir;
+                }
+            }
+        }
+        cells.push(pos);
+    }
+    return cells;
+}
+
+// 拐点所在格子朝转弯内侧的那个角
+fn turn_corner(prev: Pos, cell: Pos, next: Pos) -> Point {
+    let unit = |diff: Pos| {
+        let len = diff.length();
+        return (diff.x as f64 / len, diff.y as f64 / len);
+    };
+    let (ax, ay) = unit(cell - prev);
+    let (bx, by) = unit(next - cell);
+    let offset = |v: f64| {
+        return if v > 1e-9 {
+            0.5
+        } else if v < -1e-9 {
+            -0.5
+        } else {
+            0.0
+        };
+    };
+    let center = Point::center(cell);
+    return Point::new(center.x + offset(bx - ax), center.y + offset(by - ay));
+}
+
+// 墙角上的点算在它的拐点格子里：往格子里挪一点点再判断视线，
+// 不然会被算到格点右下方那一格，那一格常常就是墙。
+// 横竖挪得不一样多，免得45度的线段还是正好穿过这个格点
+fn inside(point: Point, cell: Option<Pos>) -> Point {
+    return match cell {
+        Some(cell) => {
+            let center = Point::center(cell);
+            Point::new(
+                point.x + (center.x - point.x) * 2e-6,
+                point.y + (center.y - point.y) * 4e-6,
+            )
+        }
+        None => point,
+    };
+}
+
+// 不许碰墙角时，墙角上的点本身就碰到了墙
+fn corner_allowed(grid: &Grid, corner: Point) -> bool {
+    if grid.diagonal() != DiagonalMode::OnlyWhenNoObstacles {
+        return true;
+    }
+    let (x, y) = (corner.x as i32, corner.y as i32);
+    return [
+        pos!(x - 1, y - 1),
+        pos!(x, y - 1),
+        pos!(x - 1, y),
+        pos!(x, y),
+    ]
+    .iter()
+    .all(|&cell| grid.can_walk(cell));
+}
+
+// 把points[start..]的拐点挪到墙角上，cells是这些拐点和前后各一格。
+// 挪过去后和前后的点之间没有视线就放回格子中心；放回去后相邻的段也变了，
+// 所以一直检查到没有变化为止。都在中心时每一段都是看得到的
+fn place_corners(grid: &Grid, points: &mut [Point], start: usize, cells: &[Pos]) {
+    let count = cells.len() - 2;
+    let mut moved = vec![false; count];
+    for k in 0..count {
+        let corner = turn_corner(cells[k], cells[k + 1], cells[k + 2]);
+        if corner_allowed(grid, corner) {
+            points[start + k] = corner;
+            moved[k] = true;
+        }
+    }
+    let turn = |moved: &[bool], i: usize| {
+        return if i >= start && i < start + count && moved[i - start] {
+            Some(cells[i - start + 1])
+        } else {
+            None
+        };
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for j in 0..points.len() - 1 {
+            let a = inside(points[j], turn(&moved, j));
+            let b = inside(points[j + 1], turn(&moved, j + 1));
+            if grid.line_of_sight_points(a, b) {
+                continue;
+            }
+            for i in [j, j + 1] {
+                if i >= start && i < start + count && moved[i - start] {
+                    moved[i - start] = false;
+                    points[i] = Point::center(cells[i - start + 1]);
+                    changed = true;
+                }
+            }
+        }
+    }
+}
+
+fn string_pull(grid: &Grid, path: &[Pos]) -> Vec<Pos> {
+    let cells = path_cells(path);
+    let mut simpath = Vec::new();
+    if cells.is_empty() {
+        return simpath;
+    }
+    simpath.push(cells[0]);
+    let mut anchor = 0_usize;
+    while anchor < cells.len() - 1 {
+        // 相邻的格子一定走得通，再尽量往后看
+        let mut last = anchor + 1;
+        while last + 1 < cells.len() && grid.line_of_sight(cells[anchor], cells[last + 1]) {
+            last += 1;
+        }
+        simpath.push(cells[last]);
+        anchor = last;
+    }
+    return simpath;
+}
+
+impl SmoothPath {
+    pub fn new() -> Self {
+        return Self {
+            mode: SmoothMode::Segments,
+            search: JpsSearch::new(),
+        };
+    }
+
+    pub fn mode(&self) -> SmoothMode {
+        return self.mode;
+    }
+
+    pub fn set_mode(&mut self, mode: SmoothMode) {
+        self.mode = mode;
+    }
+
+    fn point_add_simp(&mut self, grid: &Grid, point: Pos, end: Pos, dist: i64, from: Pos) {
+        return self
+            .search
+            .point_add_hval(grid, point, dist, from, simphfunc(point, end));
+    }
+
+    /// Searches with `search` from the cell containing `begin` to the one
+    /// containing `end`, then smooths the path into points as
+    /// [`SmoothPath::simplify_points`] does. Empty when unreachable.
+    pub fn find_points(
+        &mut self,
+        search: &mut JpsSearch,
+        grid: &Grid,
+        begin: Point,
+        end: Point,
+        waypoints: Waypoints,
+    ) -> Result<Vec<Point>, PathError> {
+        // NaN和无穷大转成格子坐标时会悄悄变成别的格子，先挡掉
+        if !(begin.x.is_finite() && begin.y.is_finite() && end.x.is_finite() && end.y.is_finite()) {
+            return Err(PathError::OutOfBounds);
+        }
+        let path = search.find(grid, begin.cell(), end.cell())?;
+        return Ok(self.simplify_points(grid, &path, begin, end, waypoints));
+    }
+
+    /// Smooths a path found between the cells containing `begin` and `end`
+    /// and turns it into points: it starts at `begin`, ends at `end`, and the
+    /// first and last segments go straight from those real positions when
+    /// there is line of sight. Empty if `path` is.
+    pub fn simplify_points(
+        &mut self,
+        grid: &Grid,
+        path: &[Pos],
+        begin: Point,
+        end: Point,
+        waypoints: Waypoints,
+    ) -> Vec<Point> {
+        let cells = self.simplify(grid, path);
+        let mut points = Vec::new();
+        if cells.is_empty() {
+            return points;
+        }
+        let n = cells.len() - 1;
+        let target = |i: usize| if i >= n { end } else { Point::center(cells[i]) };
+
+        // 中间的拐点是cells[lo..hi]，两头能直接看到的就去掉
+        let mut lo = 1;
+        while lo < n && grid.line_of_sight_points(begin, target(lo + 1)) {
+            lo += 1;
+        }
+        let mut hi = cmp::max(n, lo);
+        while hi > lo {
+            let from = if hi - 1 > lo {
+                Point::center(cells[hi - 2])
+            } else {
+                begin
+            };
+            if !grid.line_of_sight_points(from, end) {
+                break;
+            }
+            hi -= 1;
+        }
+
+        points.push(begin);
+        // 起点看不到下一个拐点时，先走到所在格子的中心
+        let first = if hi > lo { target(lo) } else { end };
+        if !grid.line_of_sight_points(begin, first) {
+            points.push(Point::center(cells[0]));
+        }
+        let turns = points.len();
+        for &cell in &cells[lo..hi] {
+            points.push(Point::center(cell));
+        }
+        // 同样，看不到终点时先走到终点所在格子的中心
+        let last = if hi > lo {
+            Point::center(cells[hi - 1])
+        } else {
+            points[points.len() - 1]
+        };
+        if !grid.line_of_sight_points(last, end) {
+            points.push(Point::center(cells[n]));
+        }
+        points.push(end);
+        if waypoints == Waypoints::Corner && hi > lo {
+            place_corners(grid, &mut points, turns, &cells[lo - 1..=hi]);
+        }
+        return points;
+    }
+
+    /// Straightens a path returned by [`JpsSearch::find`]. The result starts
+    /// and ends at the same cells and only uses segments with line of sight.
+    pub fn simplify(&mut self, grid: &Grid, path: &[Pos]) -> Vec<Pos> {
+        if self.mode == SmoothMode::StringPull {
+            return string_pull(grid, path);
+        }
+        let mut simpath = Vec::new();
+        if !path.is_empty() {
+            let mut dir = pos!(0, 0);
+            let mut begin = path[0];
+            // 这一段原路径上的点，相邻两点之间一定走得通
+            let mut chain = vec![begin];
+            let mut shorten = false;
+            let mut i = 0_usize;
+            loop {
+                let mut stop = false;
+                if i == path.len() - 1 {
+                    stop = true;
+                } else {
+                    let diff = path[i + 1] - path[i];
+                    if diff.x != 0 {
+                        if dir.x == 0 {
+                            dir.x = diff.x;
+                        } else if dir.x * diff.x < 0 {
+                            // 判断符号不同
+                            stop = true;
+                        }
+                    }
+                    if diff.y != 0 {
+                        if dir.y == 0 {
+                            dir.y = diff.y;
+                        } else if dir.y * diff.y < 0 {
+                            // 判断符号不同
+                            stop = true;
+                        }
+                    }
+                }
+
+                if stop {
+                    if shorten {
+                        #[cfg(feature = "debug")]
+                        console_log(format!("Range: {:?} - {}", begin, i).as_str());
+
+                        // simplify here
+                        {
+                            // let begin = path[i_begin];
+                            let end = path[i];
+                            let dir = (begin - end).signxy(); // 就是反过来的
+                            if dir.x == 0 || dir.y == 0 {
+                                simpath.push(begin);
+                            } else {
+                                // 先只用拐点；视线被挡住时（比如不许切墙角）再沿原路径补上
+                                for &fallback in &[false, true] {
+                                    self.search.reset(grid);
+
+                                    let mut pointlist = Vec::new();
+
+                                    let mut cpos = end;
+                                    while cpos.y * dir.y <= begin.y * dir.y {
+                                        cpos.x = end.x;
+                                        while cpos.x * dir.x <= begin.x * dir.x {
+                                            if grid.can_walk(cpos)
+                                                && grid.can_walk(cpos + dir)
+                                                && (!grid.can_walk(cpos + dir.xonly())
+                                                    || !grid.can_walk(cpos + dir.yonly()))
+                                            {
+                                                // 判断拐点
+                                                pointlist.push(cpos);
+                                                pointlist.push(cpos + dir);
+                                            }
+                                            cpos.x += dir.x;
+                                        }
+                                        cpos.y += dir.y;
+                                    }
+
+                                    if fallback {
+                                        pointlist.extend_from_slice(&chain);
+                                    } else {
+                                        pointlist.push(begin);
+                                    }
+                                    self.point_add_simp(grid, end, begin, 0, end);
+
+                                    while let Some(pinfo) = self.search.openlist.pop() {
+                                        #[cfg(feature = "debug")]
+                                        console_log(
+                                            format!("{:?} <- {:?} ", pinfo, self.search.openlist)
+                                                .as_str(),
+                                        );
+
+                                        let cpos = pinfo.position;
+                                        if cpos == begin {
+                                            break;
+                                        }
+
+                                        let dist = pinfo.distance;
+                                        let k = chain.iter().position(|&pos| pos == cpos);
+                                        if let (true, Some(k)) = (fallback, k) {
+                                            if k > 0 {
+                                                let pos = chain[k - 1];
+                                                let dist2 = simphfunc(pos, cpos);
+                                                self.point_add_simp(
+                                                    grid,
+                                                    pos,
+                                                    begin,
+                                                    dist.saturating_add(dist2),
+                                                    cpos,
+                                                );
+                                            }
+                                        }
+                                        pointlist.iter().for_each(|pos| {
+                                            let pos = *pos;
+                                            let diff = pos - cpos;
+                                            if diff.x * dir.x >= 0 && diff.y * dir.y >= 0 {
+                                                let can_go = grid.line_of_sight(cpos, pos);
+                                                if can_go {
+                                                    let dist2 = simphfunc(pos, cpos);
+                                                    self.point_add_simp(
+                                                        grid,
+                                                        pos,
+                                                        begin,
+                                                        dist.saturating_add(dist2),
+                                                        cpos,
+                                                    );
+                                                }
+                                            }
+                                        });
+
+                                        #[cfg(feature = "debug")]
+                                        console_log(self.search.debug(grid).as_str());
+                                    }
+
+                                    if self.search.distance_at(grid.index(begin)) != UNREACHED {
+                                        break;
+                                    }
+                                }
+
+                                let i = grid.index(begin);
+                                if self.search.distance_at(i) != UNREACHED {
+                                    let mut find = begin;
+                                    let mut cdir = pos!(0, 0);
+                                    while end != find {
+                                        let next = self.search.frompos_at(grid.index(find));
+                                        let dir = find - next;
+
+                                        // 如果连续三个点在同一条直线上，则不输出第二个点
+                                        if cdir == pos!(0, 0) || dir.cross(cdir) != 0 {
+                                            simpath.push(find);
+                                        }
+
+                                        find = next;
+                                        cdir = dir;
+                                    }
+                                }
+                            }
+                            if i == path.len() - 1 {
+                                break;
+                            }
+                            begin = simpath[simpath.len() - 1];
+                            chain.clear();
+                            chain.push(begin);
+                            chain.push(path[i]);
+                        }
+                        shorten = false;
+                        dir = path[i] - begin;
+                    } else {
+                        // 只有一个点的路径（起点即终点）走到这里，否则会死循环
+                        if i == path.len() - 1 {
+                            break;
+                        }
+                        begin = path[i];
+                        chain.clear();
+                        chain.push(begin);
+                        dir = pos!(0, 0);
+                    }
+                } else {
+                    shorten = true;
+                    i += 1;
+                    chain.push(path[i]);
+                }
+            }
+            simpath.push(path[path.len() - 1]);
+        }
+
+        return simpath;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{random_grid, Random, DIAGONAL_MODES};
+
+    fn grid_from(rows: &[&str], diagonal: DiagonalMode) -> Grid {
+        let map = rows
+            .iter()
+            .flat_map(|row| row.bytes().map(|c| (c == b'#') as u8))
+            .collect();
+        return Grid::with_diagonal(rows[0].len() as i32, rows.len() as i32, map, diagonal)
+            .unwrap();
+    }
+
+    // 线段有没有穿过墙的内部，只擦过边或角不算
+    fn crosses_wall(grid: &Grid, a: Point, b: Point) -> bool {
+        let near = |v: f64| (v - v.round()).abs() < 1e-7;
+        return (0..=1000).any(|i| {
+            let t = i as f64 / 1000.0;
+            let (x, y) = (a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t);
+            !near(x) && !near(y) && !grid.can_walk(Point::new(x, y).cell())
+        });
+    }
+
+    fn smooth_points(grid: &Grid, begin: Pos, end: Pos, waypoints: Waypoints) -> Vec<Point> {
+        let (begin, end) = (Point::center(begin), Point::center(end));
+        let mut search = JpsSearch::new();
+        return SmoothPath::new()
+            .find_points(&mut search, grid, begin, end, waypoints)
+            .unwrap();
+    }
+
+    #[test]
+    fn find_points_starts_and_ends_at_the_points() {
+        let grid = grid_from(&["....", ".##.", "...."], DiagonalMode::Always);
+        let mut smooth = SmoothPath::new();
+        let mut search = JpsSearch::new();
+        let (begin, end) = (Point::new(0.25, 0.75), Point::new(3.5, 2.125));
+        let points = smooth
+            .find_points(&mut search, &grid, begin, end, Waypoints::Center)
+            .unwrap();
+        assert_eq!(points.first(), Some(&begin));
+        assert_eq!(points.last(), Some(&end));
+
+        for bad in [f64::NAN, f64::INFINITY, -1.0, 4.0] {
+            let result = smooth.find_points(
+                &mut search,
+                &grid,
+                Point::new(bad, 0.5),
+                end,
+                Waypoints::Center,
+            );
+            assert_eq!(result, Err(PathError::OutOfBounds), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn corner_next_to_wall_falls_back_to_center() {
+        // 在(4,3)转弯时，右下角(5,4)连到下一个拐点会穿过(4,4)这堵墙
+        let grid = grid_from(
+            &[
+                ".#......##.",
+                "........#..",
+                "........#..",
+                "...#...#.#.",
+                "..#.#..##..",
+                "..........#",
+                ".........#.",
+            ],
+            DiagonalMode::Always,
+        );
+        let points = smooth_points(&grid, pos!(7, 1), pos!(1, 5), Waypoints::Corner);
+        assert!(!points.contains(&Point::new(5.0, 4.0)));
+        for pair in points.windows(2) {
+            assert!(!crosses_wall(&grid, pair[0], pair[1]), "{:?}", pair);
+        }
+    }
+
+    #[test]
+    fn corner_segments_never_cross_walls() {
+        let mut random = Random::new(1);
+        for round in 0..400 {
+            let size = pos!(8 + random.below(8), 8 + random.below(8));
+            let grid = random_grid(&mut random, size, 30, DIAGONAL_MODES[round % 4]);
+            let (begin, end) = (random.pos(&grid), random.pos(&grid));
+            if !grid.is_reachable(begin, end) {
+                continue;
+            }
+            for waypoints in [Waypoints::Center, Waypoints::Corner] {
+                let points = smooth_points(&grid, begin, end, waypoints);
+                for pair in points.windows(2) {
+                    assert!(!crosses_wall(&grid, pair[0], pair[1]), "{:?}", pair);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::grid::{DiagonalMode, Grid};
+use crate::pos::Pos;
+
+// 测试用的随机地图和编辑，种子固定，每次跑的结果都一样
+
+pub(crate) const DIAGONAL_MODES: [DiagonalMode; 4] = [
+    DiagonalMode::Always,
+    DiagonalMode::IfAtMostOneObstacle,
+    DiagonalMode::OnlyWhenNoObstacles,
+    DiagonalMode::Never,
+];
+
+pub(crate) struct Random(u32);
+
+impl Random {
+    pub(crate) fn new(seed: u32) -> Self {
+        return Self(seed);
+    }
+
+    // 0..n之间的数
+    pub(crate) fn below(&mut self, n: i32) -> i32 {
+        self.0 = self.0.wrapping_mul(1103515245).wrapping_add(12345);
+        return ((self.0 >> 8) % n as u32) as i32;
+    }
+
+    pub(crate) fn pos(&mut self, grid: &Grid) -> Pos {
+        return pos!(self.below(grid.width()), self.below(grid.height()));
+    }
+}
+
+// 大约percent%的格子是墙
+pub(crate) fn random_grid(
+    random: &mut Random,
+    size: Pos,
+    percent: i32,
+    diagonal: DiagonalMode,
+) -> Grid {
+    let map = (0..size.x * size.y)
+        .map(|_| (random.below(100) < percent) as u8)
+        .collect();
+    return Grid::with_diagonal(size.x, size.y, map, diagonal).unwrap();
+}
+
+// 改一格，或者改一小块；返回改动矩形的两个角
+pub(crate) fn random_edit(random: &mut Random, grid: &mut Grid) -> (Pos, Pos) {
+    let a = random.pos(grid);
+    let blocked = random.below(10) < 3;
+    if random.below(4) == 0 {
+        let b = a + pos!(random.below(7) - 3, random.below(7) - 3);
+        grid.set_rect(a, b, blocked);
+        return (a, b);
+    }
+    grid.set_cell(a, blocked).unwrap();
+    return (a, a);
+}
+
+// 同一张地图从头建一个，和编辑时增量维护的状态对照
+pub(crate) fn rebuilt(grid: &Grid) -> Grid {
+    let mut fresh = Grid::with_diagonal(
+        grid.width(),
+        grid.height(),
+        grid.map.clone(),
+        grid.diagonal(),
+    )
+    .unwrap();
+    fresh.set_terrain(grid.terrain());
+    fresh.set_corner_rule(grid.corner_rule());
+    fresh.set_jump_table(grid.has_jump_table());
+    fresh.set_bit_packed(grid.is_bit_packed());
+    return fresh;
+}
@@ -0,0 +1,549 @@
+use wasm_bindgen::prelude::*;
+
+#[cfg(feature = "debug")]
+use crate::console_log;
+use crate::error::PathError;
+use crate::flow::FlowField;
+use crate::grid::{CornerRule, DiagonalMode, Grid, Terrain};
+use crate::hpa::Hierarchy;
+use crate::line::cells_on_line;
+use crate::pos::{Point, Pos};
+use crate::search::{CostModel, JpsSearch, SearchMode, SearchState, SearchStatus, TieBreak};
+use crate::smooth::{SmoothMode, SmoothPath, Waypoints};
+
+fn flatten(path: &[Pos]) -> Vec<i32> {
+    let mut resu = Vec::with_capacity(path.len() * 2);
+    path.iter().for_each(|point| {
+        resu.push(point.x);
+        resu.push(point.y);
+    });
+    return resu;
+}
+
+/// Outcome of one query. Waypoint lists are flat `[x0, y0, x1, y1, ...]`
+/// arrays ordered from begin to end, and are empty when the goal was not
+/// reached, unless a `partial` path to the closest cell was asked for.
+#[wasm_bindgen]
+pub struct PathResult {
+    path: Vec<Pos>,
+    smoothpath: Vec<Pos>,
+    cost: f64,
+    expanded: usize,
+    goal: Option<usize>,
+    partial: bool,
+}
+
+#[wasm_bindgen]
+impl PathResult {
+    #[wasm_bindgen(getter)]
+    pub fn reached(&self) -> bool {
+        return !self.path.is_empty() && !self.partial;
+    }
+
+    /// Cost of the raw path under the pathfinder's cost model (2 per straight
+    /// step and 3 per diagonal step by default, times the terrain cost on
+    /// weighted maps), or -1 if no path was returned. For a `partial` result
+    /// this is the cost of the path to the closest cell.
+    #[wasm_bindgen(getter)]
+    pub fn cost(&self) -> f64 {
+        return self.cost;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn nodes_expanded(&self) -> usize {
+        return self.expanded;
+    }
+
+    /// `true` when the path stops short of the goal, at the closest cell the
+    /// search got to.
+    #[wasm_bindgen(getter)]
+    pub fn partial(&self) -> bool {
+        return self.partial;
+    }
+
+    /// Index of the goal reached by `find_nearest` (`0` for `find`), or -1
+    /// if not reached.
+    #[wasm_bindgen(getter)]
+    pub fn goal_index(&self) -> i32 {
+        return self.goal.map_or(-1, |goal| goal as i32);
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn path(&self) -> Vec<i32> {
+        return flatten(&self.path);
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn smooth_path(&self) -> Vec<i32> {
+        return flatten(&self.smoothpath);
+    }
+}
+
+#[wasm_bindgen]
+impl FlowField {
+    #[wasm_bindgen(getter, js_name = width)]
+    pub fn field_width(&self) -> i32 {
+        return self.width();
+    }
+
+    #[wasm_bindgen(getter, js_name = height)]
+    pub fn field_height(&self) -> i32 {
+        return self.height();
+    }
+
+    /// Costs row by row as a `Float64Array`, `Infinity` where the goal
+    /// cannot be reached.
+    #[wasm_bindgen(getter, js_name = distances)]
+    pub fn distance_array(&self) -> Vec<f64> {
+        return self.distances().to_vec();
+    }
+
+    /// Steps row by row as an `Int8Array` of `[dx, dy]` pairs, `[0, 0]` at
+    /// the goal and where it cannot be reached.
+    #[wasm_bindgen(getter, js_name = directions)]
+    pub fn direction_array(&self) -> Vec<i8> {
+        return self.directions().to_vec();
+    }
+}
+
+/// JS handle that owns a [`Grid`] together with the search and smoothing
+/// scratch buffers, so repeated queries on the same level reuse them.
+#[wasm_bindgen]
+pub struct Pathfinder {
+    grid: Grid,
+    search: JpsSearch,
+    smoother: SmoothPath,
+    stepping: Option<SearchState>,
+    hierarchy: Option<Hierarchy>,
+}
+
+#[wasm_bindgen]
+impl Pathfinder {
+    #[wasm_bindgen(constructor)]
+    pub fn new(map: &[u8], map_x: i32, map_y: i32) -> Result<Pathfinder, PathError> {
+        #[cfg(feature = "console_error_panic_hook")]
+        console_error_panic_hook::set_once();
+
+        return Ok(Pathfinder {
+            grid: Grid::new(map_x, map_y, map.to_vec())?,
+            search: JpsSearch::new(),
+            smoother: SmoothPath::new(),
+            stepping: None,
+            hierarchy: None,
+        });
+    }
+
+    /// Same as the constructor, with the movement rules set up front.
+    /// `DiagonalMode.Never` gives 4-connected paths made of axis-aligned segments.
+    pub fn with_diagonal_mode(
+        map: &[u8],
+        map_x: i32,
+        map_y: i32,
+        diagonal: DiagonalMode,
+    ) -> Result<Pathfinder, PathError> {
+        let mut pathfinder = Pathfinder::new(map, map_x, map_y)?;
+        pathfinder.grid.set_diagonal(diagonal);
+        return Ok(pathfinder);
+    }
+
+    pub fn set_cell(&mut self, x: i32, y: i32, blocked: bool) -> Result<(), PathError> {
+        self.grid.set_cell(pos!(x, y), blocked)?;
+        self.update_hierarchy(pos!(x, y), pos!(x, y));
+        return Ok(());
+    }
+
+    pub fn set_rect(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, blocked: bool) {
+        self.grid.set_rect(pos!(x0, y0), pos!(x1, y1), blocked);
+        self.update_hierarchy(pos!(x0, y0), pos!(x1, y1));
+    }
+
+    pub fn load(&mut self, map: &[u8]) -> Result<(), PathError> {
+        self.grid.load(map)?;
+        self.rebuild_hierarchy();
+        return Ok(());
+    }
+
+    /// Writes a raw cell value, e.g. a terrain cost on weighted maps.
+    pub fn set_value(&mut self, x: i32, y: i32, value: u8) -> Result<(), PathError> {
+        self.grid.set_value(pos!(x, y), value)?;
+        self.update_hierarchy(pos!(x, y), pos!(x, y));
+        return Ok(());
+    }
+
+    /// Reads cells as `0` walkable, anything else a wall (the default).
+    pub fn use_binary_terrain(&mut self) {
+        self.grid.set_terrain(Terrain::Binary);
+        self.rebuild_hierarchy();
+    }
+
+    /// Reads cells equal to `blocked` as walls and every other value as the
+    /// cost of entering the cell. Weighted maps are always searched with A*.
+    pub fn use_weighted_terrain(&mut self, blocked: u8) {
+        self.grid.set_terrain(Terrain::Weighted { blocked });
+        self.rebuild_hierarchy();
+    }
+
+    pub fn set_diagonal_mode(&mut self, diagonal: DiagonalMode) {
+        self.grid.set_diagonal(diagonal);
+        self.rebuild_hierarchy();
+    }
+
+    /// Whether a line of sight through a grid corner is blocked by the walls
+    /// touching it. `CornerRule.FollowDiagonal`, the default, decides as for
+    /// a diagonal step; see [`Grid::set_corner_rule`].
+    pub fn set_corner_rule(&mut self, corners: CornerRule) {
+        self.grid.set_corner_rule(corners);
+    }
+
+    /// Precomputes jump distances for `SearchMode.JpsPlus`, kept up to date
+    /// as the map is edited. Costs 32 bytes per cell.
+    pub fn set_jump_table(&mut self, enabled: bool) {
+        self.grid.set_jump_table(enabled);
+    }
+
+    /// Keeps a bit-packed copy of the map so that straight jumps scan 64
+    /// cells at a time. Costs 2 bits per cell.
+    pub fn set_bit_packed(&mut self, enabled: bool) {
+        self.grid.set_bit_packed(enabled);
+    }
+
+    /// Builds a hierarchy of `cluster_size` x `cluster_size` clusters for
+    /// `find_hierarchical`. It is kept up to date as the map is edited.
+    pub fn build_hierarchy(&mut self, cluster_size: i32) -> Result<(), PathError> {
+        let costs = self.search.cost_model();
+        let mut hierarchy = Hierarchy::with_cost_model(&self.grid, cluster_size, costs)?;
+        hierarchy.set_tie_break(self.search.tie_break());
+        self.hierarchy = Some(hierarchy);
+        return Ok(());
+    }
+
+    pub fn drop_hierarchy(&mut self) {
+        self.hierarchy = None;
+    }
+
+    /// Like `find`, over the hierarchy built by `build_hierarchy`: much less
+    /// work on large maps, for a path that may be slightly longer. Same as
+    /// `find` when there is no hierarchy.
+    pub fn find_hierarchical(
+        &mut self,
+        begin_x: i32,
+        begin_y: i32,
+        end_x: i32,
+        end_y: i32,
+    ) -> Result<PathResult, PathError> {
+        let hierarchy = match &mut self.hierarchy {
+            Some(hierarchy) => hierarchy,
+            None => return self.find(begin_x, begin_y, end_x, end_y),
+        };
+        let path = hierarchy.find(&self.grid, pos!(begin_x, begin_y), pos!(end_x, end_y))?;
+        let smoothpath = self.smoother.simplify(&self.grid, &path);
+        return Ok(PathResult {
+            cost: hierarchy.cost().unwrap_or(-1.0),
+            expanded: hierarchy.nodes_expanded(),
+            goal: hierarchy.cost().map(|_| 0),
+            partial: false,
+            path,
+            smoothpath,
+        });
+    }
+
+    pub fn set_search_mode(&mut self, mode: SearchMode) {
+        self.search.set_mode(mode);
+    }
+
+    pub fn set_smooth_mode(&mut self, mode: SmoothMode) {
+        self.smoother.set_mode(mode);
+    }
+
+    /// Caps the nodes expanded by one query; past it the best path so far is
+    /// returned with `partial` set. `undefined` removes the cap.
+    pub fn set_max_expansions(&mut self, limit: Option<u32>) {
+        self.search
+            .set_max_expansions(limit.map(|limit| limit as usize));
+    }
+
+    /// When the goal cannot be reached, return a path to the reachable cell
+    /// closest to it, with `partial` set, instead of an empty one.
+    pub fn set_fallback_to_closest(&mut self, fallback: bool) {
+        self.search.set_fallback_to_closest(fallback);
+    }
+
+    /// Starts a search that is run a slice at a time with `step`, so a long
+    /// query can be spread over several frames. Replaces any search already
+    /// in progress. The map must not be edited until it is done.
+    pub fn start_search(
+        &mut self,
+        begin_x: i32,
+        begin_y: i32,
+        end_x: i32,
+        end_y: i32,
+    ) -> Result<(), PathError> {
+        let (begin, end) = (pos!(begin_x, begin_y), pos!(end_x, end_y));
+        // 上一次的缓冲区接着用
+        match &mut self.stepping {
+            Some(state) => self.search.restart(state, &self.grid, begin, end)?,
+            None => self.stepping = Some(self.search.start(&self.grid, begin, end)?),
+        }
+        return Ok(());
+    }
+
+    /// Expands at most `n` more nodes of the search started by `start_search`.
+    pub fn step(&mut self, n: u32) -> SearchStatus {
+        return match &mut self.stepping {
+            Some(state) => state.step(&self.grid, n as usize),
+            None => SearchStatus::Unreachable,
+        };
+    }
+
+    /// The result of the stepped search: the path once found, the best
+    /// path so far while it is still running.
+    pub fn search_result(&mut self) -> PathResult {
+        let state = match &mut self.stepping {
+            Some(state) => state,
+            None => return self.result(Vec::new()),
+        };
+        let path = state.path(&self.grid);
+        let search = state.search();
+        let smoothpath = self.smoother.simplify(&self.grid, &path);
+        return PathResult {
+            cost: search.cost().unwrap_or(-1.0),
+            expanded: search.nodes_expanded(),
+            goal: search.reached_goal(),
+            partial: search.is_partial(),
+            path,
+            smoothpath,
+        };
+    }
+
+    /// Costs 2 per straight step and 3 per diagonal step (the default).
+    pub fn use_integer_costs(&mut self) -> Result<(), PathError> {
+        return self.set_cost_model(CostModel::Integer);
+    }
+
+    /// Fixed-point octile costs, e.g. `1000` and `1414`. Throws
+    /// `BadCostModel` unless `0 < straight <= diagonal < 2 * straight`.
+    pub fn use_fixed_costs(&mut self, straight: u32, diagonal: u32) -> Result<(), PathError> {
+        return self.set_cost_model(CostModel::FixedOctile { straight, diagonal });
+    }
+
+    /// Costs 1 per straight step and √2 per diagonal step.
+    pub fn use_float_costs(&mut self) -> Result<(), PathError> {
+        return self.set_cost_model(CostModel::Float);
+    }
+
+    /// Which of several equally promising cells to expand first; paths only
+    /// depend on the map and the query, never on the order cells were found.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.search.set_tie_break(tie_break);
+        if let Some(hierarchy) = &mut self.hierarchy {
+            hierarchy.set_tie_break(tie_break);
+        }
+    }
+
+    /// Whether any path joins the two cells, answered without a search.
+    pub fn is_reachable(&self, x0: i32, y0: i32, x1: i32, y1: i32) -> bool {
+        return self.grid.is_reachable(pos!(x0, y0), pos!(x1, y1));
+    }
+
+    /// Label of the connected region of a cell, `undefined` for walls and
+    /// outside the map. Labels may change when the map is edited.
+    pub fn component_of(&self, x: i32, y: i32) -> Option<u32> {
+        return self.grid.component_of(pos!(x, y));
+    }
+
+    /// See [`Grid::line_of_sight`].
+    pub fn line_of_sight(&self, x0: i32, y0: i32, x1: i32, y1: i32) -> bool {
+        return self.grid.line_of_sight(pos!(x0, y0), pos!(x1, y1));
+    }
+
+    /// Cells touched by the segment between two cell centres, as a flat
+    /// `[x0, y0, x1, y1, ...]` array. See [`cells_on_line`].
+    pub fn cells_on_line(&self, x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<i32> {
+        return flatten(&cells_on_line(pos!(x0, y0), pos!(x1, y1)));
+    }
+
+    pub fn find(
+        &mut self,
+        begin_x: i32,
+        begin_y: i32,
+        end_x: i32,
+        end_y: i32,
+    ) -> Result<PathResult, PathError> {
+        let path = self
+            .search
+            .find(&self.grid, pos!(begin_x, begin_y), pos!(end_x, end_y))?;
+        return Ok(self.result(path));
+    }
+
+    /// Path to whichever of `goals`, a flat `[x0, y0, x1, y1, ...]` array,
+    /// is cheapest to reach, found in one search. See `PathResult.goal_index`.
+    pub fn find_nearest(
+        &mut self,
+        begin_x: i32,
+        begin_y: i32,
+        goals: &[i32],
+    ) -> Result<PathResult, PathError> {
+        let goals = goals
+            .chunks_exact(2)
+            .map(|xy| pos!(xy[0], xy[1]))
+            .collect::<Vec<_>>();
+        let path = self
+            .search
+            .find_nearest(&self.grid, pos!(begin_x, begin_y), &goals)?;
+        return Ok(self.result(path));
+    }
+
+    /// One path per start, a flat `[x0, y0, x1, y1, ...]` array, all heading
+    /// to the same goal and found by growing a single search from it.
+    /// `nodes_expanded` is the total for the whole batch.
+    pub fn find_many(
+        &mut self,
+        starts: &[i32],
+        goal_x: i32,
+        goal_y: i32,
+    ) -> Result<Vec<PathResult>, PathError> {
+        let starts = starts
+            .chunks_exact(2)
+            .map(|xy| pos!(xy[0], xy[1]))
+            .collect::<Vec<_>>();
+        let paths = self
+            .search
+            .find_many(&self.grid, &starts, pos!(goal_x, goal_y))?;
+        let expanded = self.search.nodes_expanded();
+        let mut resu = Vec::with_capacity(paths.len());
+        for (path, &begin) in paths.into_iter().zip(starts.iter()) {
+            let cost = self.search.cost_to(&self.grid, begin);
+            let smoothpath = self.smoother.simplify(&self.grid, &path);
+            resu.push(PathResult {
+                goal: cost.map(|_| 0),
+                cost: cost.unwrap_or(-1.0),
+                path,
+                smoothpath,
+                expanded,
+                partial: false,
+            });
+        }
+        return Ok(resu);
+    }
+
+    /// Cost and first step towards the goal for every cell. See `FlowField`.
+    pub fn flow_field(&mut self, goal_x: i32, goal_y: i32) -> Result<FlowField, PathError> {
+        return self.search.flow_field(&self.grid, pos!(goal_x, goal_y));
+    }
+
+    /// Path between two points anywhere inside the map, searched from the
+    /// cells that contain them. Returns a flat `[x0, y0, x1, y1, ...]`
+    /// `Float64Array` from `begin` to `end`, smoothed, with turning points
+    /// placed according to `waypoints`. Empty when unreachable.
+    pub fn find_points(
+        &mut self,
+        begin_x: f64,
+        begin_y: f64,
+        end_x: f64,
+        end_y: f64,
+        waypoints: Waypoints,
+    ) -> Result<Vec<f64>, PathError> {
+        let begin = Point::new(begin_x, begin_y);
+        let end = Point::new(end_x, end_y);
+        let points =
+            self.smoother
+                .find_points(&mut self.search, &self.grid, begin, end, waypoints)?;
+        let mut resu = Vec::with_capacity(points.len() * 2);
+        points.iter().for_each(|point| {
+            resu.push(point.x);
+            resu.push(point.y);
+        });
+        return Ok(resu);
+    }
+
+    /// Fast path for hot loops: only the requested waypoint list, as a flat
+    /// `Int32Array`, without building a `PathResult`. Empty when unreachable.
+    pub fn find_flat(
+        &mut self,
+        begin_x: i32,
+        begin_y: i32,
+        end_x: i32,
+        end_y: i32,
+        smooth: bool,
+    ) -> Result<Vec<i32>, PathError> {
+        let path = self
+            .search
+            .find(&self.grid, pos!(begin_x, begin_y), pos!(end_x, end_y))?;
+        if smooth {
+            return Ok(flatten(&self.smoother.simplify(&self.grid, &path)));
+        }
+        return Ok(flatten(&path));
+    }
+}
+
+impl Pathfinder {
+    fn update_hierarchy(&mut self, a: Pos, b: Pos) {
+        if let Some(hierarchy) = &mut self.hierarchy {
+            hierarchy.update(&self.grid, a, b);
+        }
+    }
+
+    fn set_cost_model(&mut self, costs: CostModel) -> Result<(), PathError> {
+        self.search.set_cost_model(costs)?;
+        self.rebuild_hierarchy();
+        return Ok(());
+    }
+
+    // 代价模型也要跟着换，所以整个重建
+    fn rebuild_hierarchy(&mut self) {
+        if let Some(hierarchy) = &self.hierarchy {
+            let size = hierarchy.cluster_size();
+            self.hierarchy =
+                Hierarchy::with_cost_model(&self.grid, size, self.search.cost_model()).ok();
+            if let Some(hierarchy) = &mut self.hierarchy {
+                hierarchy.set_tie_break(self.search.tie_break());
+            }
+        }
+    }
+
+    fn result(&mut self, path: Vec<Pos>) -> PathResult {
+        let grid = &self.grid;
+
+        #[cfg(feature = "debug")]
+        {
+            console_log(self.search.debug(grid).as_str());
+            console_log(self.search.debug_path(grid, &path).as_str());
+            console_log(format!("{:?}\n", path).as_str());
+        }
+
+        let cost = self.search.cost().unwrap_or(-1.0);
+        let expanded = self.search.nodes_expanded();
+        let goal = self.search.reached_goal();
+        let partial = self.search.is_partial();
+        let smoothpath = self.smoother.simplify(grid, &path);
+
+        #[cfg(feature = "debug")]
+        {
+            console_log(self.search.debug_path(grid, &smoothpath).as_str());
+            console_log(format!("{:?}\n", smoothpath).as_str());
+        }
+
+        return PathResult {
+            path,
+            smoothpath,
+            cost,
+            expanded,
+            goal,
+            partial,
+        };
+    }
+}
+
+#[wasm_bindgen]
+pub fn a_star_jps(
+    map: &[u8],
+    map_x: i32,
+    map_y: i32,
+    begin_x: i32,
+    begin_y: i32,
+    end_x: i32,
+    end_y: i32,
+) -> Result<PathResult, PathError> {
+    let mut pathfinder = Pathfinder::new(map, map_x, map_y)?;
+    return pathfinder.find(begin_x, begin_y, end_x, end_y);
+}
@@ -13,4 +13,15 @@ map = `
 let x = 8;
 
 let mapp = new Uint8Array(map.split('').filter(x => x == "0" || x == "1").map(x => Number(x)));
-console.log(woj.a_star_jps(mapp, x, mapp.length / x, 7, 0, 7, 4, false));
+let pathfinder = new woj.Pathfinder(mapp, x, mapp.length / x);
+for (let [bx, by, ex, ey] of [[7, 0, 7, 4], [0, 0, 7, 7]]) {
+	let resu = pathfinder.find(bx, by, ex, ey);
+	console.log(resu.reached, resu.cost, resu.nodes_expanded, resu.path, resu.smooth_path);
+	resu.free();
+}
+try {
+	pathfinder.find(0, 0, 3, 4);
+} catch (e) {
+	console.log("error:", woj.PathError[e]);
+}
+pathfinder.free();